@@ -1,24 +1,33 @@
-use crate::{
-    files::{self, list_folders_in_path},
-    variants::Variant,
-};
+use crate::{files::list_folders_in_path, lutris, paths, steam, variants::Variant};
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::path::PathBuf;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum App {
     Steam,
     Lutris,
-    // TODO:  HeroicGamesLauncher,
+    #[serde(rename = "heroic")]
+    HeroicGamesLauncher,
+    Bottles,
 }
 
 // APP_VARIANTS is a shorthand to all app variants
-pub static APP_VARIANTS: &[App] = &[App::Steam, App::Lutris];
+pub static APP_VARIANTS: &[App] = &[
+    App::Steam,
+    App::Lutris,
+    App::HeroicGamesLauncher,
+    App::Bottles,
+];
 
 impl fmt::Display for App {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Self::Steam => write!(f, "Steam"),
             Self::Lutris => write!(f, "Lutris"),
+            Self::HeroicGamesLauncher => write!(f, "Heroic Games Launcher"),
+            Self::Bottles => write!(f, "Bottles"),
         }
     }
 }
@@ -28,34 +37,68 @@ impl App {
         match *self {
             Self::Steam => Variant::GEProton,
             Self::Lutris => Variant::WineGE,
+            Self::HeroicGamesLauncher => Variant::GEProton,
+            Self::Bottles => Variant::WineGE,
+        }
+    }
+
+    /// Returns all variants that can be installed for the app, the default one first
+    pub fn compatible_variants(&self) -> Vec<Variant> {
+        match *self {
+            Self::Steam => vec![
+                Variant::GEProton,
+                Variant::Luxtorpeda,
+                Variant::Boxtron,
+                Variant::ProtonTkg,
+            ],
+            Self::Lutris | Self::Bottles => vec![Variant::WineGE, Variant::Kron4ekWine],
+            Self::HeroicGamesLauncher => vec![
+                Variant::GEProton,
+                Variant::WineGE,
+                Variant::ProtonTkg,
+                Variant::Kron4ekWine,
+            ],
         }
     }
 
     pub fn app_installations(&self) -> Vec<AppInstallations> {
         match *self {
-            Self::Steam => vec![AppInstallations::Steam, AppInstallations::SteamFlatpak],
+            Self::Steam => vec![
+                AppInstallations::Steam,
+                AppInstallations::SteamFlatpak,
+                AppInstallations::SteamSnap,
+            ],
             Self::Lutris => vec![AppInstallations::Lutris, AppInstallations::LutrisFlatpak],
+            Self::HeroicGamesLauncher => vec![
+                AppInstallations::HeroicProton,
+                AppInstallations::HeroicProtonFlatpak,
+                AppInstallations::HeroicWine,
+                AppInstallations::HeroicWineFlatpak,
+            ],
+            Self::Bottles => vec![AppInstallations::Bottles, AppInstallations::BottlesFlatpak],
         }
     }
 
     pub fn detect_installation_method(&self) -> Vec<AppInstallations> {
-        match *self {
-            Self::Steam => {
-                detect_installations(&[AppInstallations::Steam, AppInstallations::SteamFlatpak])
-            }
-            Self::Lutris => {
-                detect_installations(&[AppInstallations::Lutris, AppInstallations::LutrisFlatpak])
-            }
-        }
+        detect_installations(&self.app_installations())
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Named in snake_case, like `steam_flatpak` or `heroic_wine`, in the config file, the JSON output and `--installation`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AppInstallations {
     Steam,
     SteamFlatpak,
+    SteamSnap,
     Lutris,
     LutrisFlatpak,
+    HeroicProton,
+    HeroicProtonFlatpak,
+    HeroicWine,
+    HeroicWineFlatpak,
+    Bottles,
+    BottlesFlatpak,
 }
 
 impl fmt::Display for AppInstallations {
@@ -63,41 +106,97 @@ impl fmt::Display for AppInstallations {
         match *self {
             Self::Steam => write!(f, "Steam \"Native\" "),
             Self::SteamFlatpak => write!(f, "Steam Flatpak"),
+            Self::SteamSnap => write!(f, "Steam Snap"),
             Self::Lutris => write!(f, "Lutris \"Native\""),
             Self::LutrisFlatpak => write!(f, "Lutris Flatpak"),
+            Self::HeroicProton => write!(f, "Heroic Proton \"Native\""),
+            Self::HeroicProtonFlatpak => write!(f, "Heroic Proton Flatpak"),
+            Self::HeroicWine => write!(f, "Heroic Wine \"Native\""),
+            Self::HeroicWineFlatpak => write!(f, "Heroic Wine Flatpak"),
+            Self::Bottles => write!(f, "Bottles \"Native\""),
+            Self::BottlesFlatpak => write!(f, "Bottles Flatpak"),
         }
     }
 }
 
 impl AppInstallations {
-    pub fn default_install_dir(&self) -> &'static str {
+    /// Returns the directory Wine/Proton versions are installed into.
+    /// Lutris' runner directory is read from its configuration, the other apps use their default layout
+    pub fn default_install_dir(&self) -> PathBuf {
         match *self {
-            Self::Steam => "~/.steam/steam/compatibilitytools.d/",
-            Self::SteamFlatpak => {
-                "~/.var/app/com.valvesoftware.Steam/data/Steam/compatibilitytools.d/"
+            Self::Steam | Self::SteamFlatpak | Self::SteamSnap => {
+                self.app_base_dir().join("compatibilitytools.d")
+            }
+            Self::Lutris => lutris::wine_runners_dir(false),
+            Self::LutrisFlatpak => lutris::wine_runners_dir(true),
+            Self::HeroicProton | Self::HeroicProtonFlatpak => {
+                self.app_base_dir().join("tools/proton")
             }
-            Self::Lutris => "~/.local/share/lutris/runners/wine/",
-            Self::LutrisFlatpak => "~/.var/app/net.lutris.Lutris/data/lutris/runners/wine/",
+            Self::HeroicWine | Self::HeroicWineFlatpak => self.app_base_dir().join("tools/wine"),
+            Self::Bottles | Self::BottlesFlatpak => self.app_base_dir().join("runners"),
         }
     }
 
-    pub fn app_base_dir(&self) -> &'static str {
+    /// Returns the base directory of the installation, used to detect if the app is installed.
+    /// Native installations honor the XDG base directories, Flatpaks live in their sandbox under `~/.var/app`.
+    /// The native Steam root is resolved from disk, falling back to `~/.steam/steam` when it can't be found
+    pub fn app_base_dir(&self) -> PathBuf {
         match *self {
-            Self::Steam => "~/.steam/steam/",
-            Self::SteamFlatpak => "~/.var/app/com.valvesoftware.Steam/data/Steam/",
-            Self::Lutris => "~/.local/share/lutris/",
-            Self::LutrisFlatpak => "~/.var/app/net.lutris.Lutris/data/lutris/",
+            Self::Steam => {
+                steam::find_steam_root().unwrap_or_else(|| paths::home_dir().join(".steam/steam"))
+            }
+            Self::SteamFlatpak => {
+                paths::flatpak_app_dir("com.valvesoftware.Steam").join("data/Steam")
+            }
+            Self::SteamSnap => paths::home_dir().join("snap/steam/common/.steam/steam"),
+            Self::Lutris => lutris::data_dir(false),
+            Self::LutrisFlatpak => lutris::data_dir(true),
+            Self::HeroicProton | Self::HeroicWine => paths::config_home().join("heroic"),
+            Self::HeroicProtonFlatpak | Self::HeroicWineFlatpak => {
+                paths::flatpak_app_dir("com.heroicgameslauncher.hgl").join("config/heroic")
+            }
+            Self::Bottles => paths::data_home().join("bottles"),
+            Self::BottlesFlatpak => {
+                paths::flatpak_app_dir("com.usebottles.bottles").join("data/bottles")
+            }
         }
     }
 
     pub fn list_installed_versions(&self) -> Result<Vec<String>, anyhow::Error> {
-        list_folders_in_path(self.default_install_dir())
+        list_folders_in_path(&self.default_install_dir())
     }
 
     pub fn into_app(&self) -> App {
         match *self {
-            Self::Steam | Self::SteamFlatpak => App::Steam,
+            Self::Steam | Self::SteamFlatpak | Self::SteamSnap => App::Steam,
             Self::Lutris | Self::LutrisFlatpak => App::Lutris,
+            Self::HeroicProton
+            | Self::HeroicProtonFlatpak
+            | Self::HeroicWine
+            | Self::HeroicWineFlatpak => App::HeroicGamesLauncher,
+            Self::Bottles | Self::BottlesFlatpak => App::Bottles,
+        }
+    }
+
+    /// Returns the variant that belongs in this installation's directory.
+    /// Heroic keeps Proton and Wine runners in separate folders, so it can't rely on the App default alone
+    pub fn app_wine_version(&self) -> Variant {
+        match *self {
+            Self::HeroicWine | Self::HeroicWineFlatpak => Variant::WineGE,
+            _ => self.into_app().app_wine_version(),
+        }
+    }
+
+    /// Returns all variants that can be installed into this installation's directory, the default one first
+    pub fn compatible_variants(&self) -> Vec<Variant> {
+        match *self {
+            Self::HeroicProton | Self::HeroicProtonFlatpak => {
+                vec![Variant::GEProton, Variant::ProtonTkg]
+            }
+            Self::HeroicWine | Self::HeroicWineFlatpak => {
+                vec![Variant::WineGE, Variant::Kron4ekWine]
+            }
+            _ => self.into_app().compatible_variants(),
         }
     }
 }
@@ -111,7 +210,7 @@ pub fn list_installed_apps() -> Vec<AppInstallations> {
 fn detect_installations(app_installations: &[AppInstallations]) -> Vec<AppInstallations> {
     app_installations
         .iter()
-        .filter(|app| files::check_if_exists(app.app_base_dir(), ""))
+        .filter(|app| app.app_base_dir().is_dir())
         .cloned()
         .collect()
 }
@@ -120,6 +219,13 @@ fn detect_installations(app_installations: &[AppInstallations]) -> Vec<AppInstal
 pub static APP_INSTALLATIONS_VARIANTS: &[AppInstallations] = &[
     AppInstallations::Steam,
     AppInstallations::SteamFlatpak,
+    AppInstallations::SteamSnap,
     AppInstallations::Lutris,
     AppInstallations::LutrisFlatpak,
+    AppInstallations::HeroicProton,
+    AppInstallations::HeroicProtonFlatpak,
+    AppInstallations::HeroicWine,
+    AppInstallations::HeroicWineFlatpak,
+    AppInstallations::Bottles,
+    AppInstallations::BottlesFlatpak,
 ];
@@ -0,0 +1,337 @@
+use crate::{
+    apps::{App, AppInstallations},
+    constants, paths, utils,
+    variants::{self, CustomVariant, Variant},
+};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// User configuration, read from `$XDG_CONFIG_HOME/protonup/config.toml`.
+/// Every field is optional, a missing file is the same as an empty one
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Apps Quick Update installs for, every detected app when empty
+    pub default_apps: Vec<App>,
+    /// Name of the variant installed by default for each app, like "GEProton" or the name of a custom variant
+    pub preferred_variants: HashMap<App, String>,
+    /// Install directories replacing the default directory of each installation, like `steam_flatpak` or `heroic_wine`
+    pub install_dirs: HashMap<AppInstallations, PathBuf>,
+    /// Number of versions of a variant Quick Update keeps per installation, older ones are removed. Keeps all of them when unset.
+    /// At least 1, the version just installed is always kept
+    pub keep_versions: Option<usize>,
+    /// Directory the archives installed into several directories are stored in until unpacked, `$TMPDIR` when unset
+    pub temp_dir: Option<PathBuf>,
+    /// Extra variants declared by the user, installed like the built-in ones
+    pub custom_variants: Vec<CustomVariant>,
+    /// Release tag Quick Update installs for the preferred variant of each app, instead of the latest one
+    pub pinned_versions: HashMap<App, String>,
+    /// Release tag Quick Update installs for a variant, by variant name, instead of the latest one
+    pub pinned_variant_versions: HashMap<String, String>,
+    /// GitHub API token, raising the rate limit of the API. `$GITHUB_TOKEN` wins over it
+    pub github_token: Option<String>,
+}
+
+impl Config {
+    /// Returns the path of the config file
+    pub fn config_file() -> PathBuf {
+        paths::config_home().join(constants::CONFIG_FILE)
+    }
+
+    /// Loads the config file, returning the default config if it doesn't exist
+    pub fn load() -> Result<Config> {
+        Config::load_from(&Config::config_file())
+    }
+
+    /// Loads a config file from a path, returning the default config if it doesn't exist
+    pub fn load_from(path: &Path) -> Result<Config> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let content = fs::read_to_string(path).with_context(|| {
+            format!(
+                "[Config] Failed to read config file : {}",
+                path.to_string_lossy()
+            )
+        })?;
+        Config::parse(&content).with_context(|| {
+            format!(
+                "[Config] Failed to parse config file : {}",
+                path.to_string_lossy()
+            )
+        })
+    }
+
+    /// Parses the content of a config file
+    pub fn parse(content: &str) -> Result<Config> {
+        let config: Config = toml::from_str(content)?;
+        if config.keep_versions == Some(0) {
+            return Err(anyhow!(
+                "keep_versions must be at least 1, remove it to keep every version"
+            ));
+        }
+        Ok(config)
+    }
+
+    /// Returns the built-in variants followed by the custom ones
+    pub fn all_variants(&self) -> Vec<Variant> {
+        variants::ALL_VARIANTS
+            .iter()
+            .cloned()
+            .chain(
+                self.custom_variants
+                    .iter()
+                    .cloned()
+                    .map(Box::new)
+                    .map(Variant::Custom),
+            )
+            .collect()
+    }
+
+    /// Returns the custom variants that target the app of the installation.
+    /// Heroic keeps Proton and Wine builds apart, custom variants for it go into its Proton directory
+    pub fn custom_variants_for(&self, app_inst: &AppInstallations) -> Vec<Variant> {
+        if let AppInstallations::HeroicWine | AppInstallations::HeroicWineFlatpak = app_inst {
+            return vec![];
+        }
+        self.custom_variants
+            .iter()
+            .filter(|custom| custom.target_apps.contains(&app_inst.into_app()))
+            .cloned()
+            .map(|custom| Variant::Custom(Box::new(custom)))
+            .collect()
+    }
+
+    /// Returns the built-in or custom variant with the given name
+    pub fn find_variant(&self, name: &str) -> Option<Variant> {
+        self.all_variants()
+            .into_iter()
+            .find(|variant| variant.to_string() == name)
+    }
+
+    /// Returns the variant to install into the installation by default.
+    /// The preferred variant of the app is used when it can be installed there, the app's default otherwise
+    pub fn preferred_variant(&self, app_inst: &AppInstallations) -> Variant {
+        self.preferred_variants
+            .get(&app_inst.into_app())
+            .and_then(|name| {
+                self.compatible_variants(app_inst)
+                    .into_iter()
+                    .find(|variant| &variant.to_string() == name)
+            })
+            .unwrap_or_else(|| app_inst.app_wine_version())
+    }
+
+    /// Returns the release tag pinned for a variant installed into the installation, if any.
+    /// A tag pinned for the app applies to its preferred variant, and wins over one pinned for the variant
+    pub fn pinned_version(&self, app_inst: &AppInstallations, variant: &Variant) -> Option<&str> {
+        self.pinned_versions
+            .get(&app_inst.into_app())
+            .filter(|_| self.preferred_variant(app_inst).to_string() == variant.to_string())
+            .or_else(|| self.pinned_variant_versions.get(&variant.to_string()))
+            .map(String::as_str)
+    }
+
+    /// Returns the directory to install into, the configured one for the installation or its default
+    pub fn install_dir(&self, app_inst: &AppInstallations) -> PathBuf {
+        self.install_dirs
+            .get(app_inst)
+            .and_then(utils::expand_tilde)
+            .unwrap_or_else(|| app_inst.default_install_dir())
+    }
+
+    /// Returns true if Quick Update should install for the app
+    pub fn is_default_app(&self, app: App) -> bool {
+        self.default_apps.is_empty() || self.default_apps.contains(&app)
+    }
+
+    /// Returns the GitHub API token from `$GITHUB_TOKEN` or the config, if any
+    pub fn github_token(&self) -> Option<String> {
+        env::var("GITHUB_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .or_else(|| self.github_token.clone())
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Returns the directory archives are stored in when they are unpacked into several directories
+    pub fn temp_dir(&self) -> PathBuf {
+        self.temp_dir
+            .as_ref()
+            .and_then(utils::expand_tilde)
+            .unwrap_or_else(paths::temp_dir)
+    }
+
+    /// Returns the variants that can be installed into the installation, built-in ones first
+    pub fn compatible_variants(&self, app_inst: &AppInstallations) -> Vec<Variant> {
+        let mut variants = app_inst.compatible_variants();
+        variants.extend(self.custom_variants_for(app_inst));
+        variants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apps::App;
+
+    #[test]
+    fn test_parse_custom_variants() {
+        let config = Config::parse(
+            r#"
+[[custom_variants]]
+name = "GE-Proton-patched"
+repository_account = "our-org"
+repository_name = "proton-ge-custom"
+asset_pattern = "GE-Proton*.tar.gz"
+checksum_pattern = "GE-Proton*.sha512sum"
+target_apps = ["steam", "heroic"]
+"#,
+        );
+        assert!(config.is_ok(), "test: Config::parse returned error");
+        let config = config.unwrap();
+
+        assert_eq!(config.custom_variants.len(), 1);
+        let custom = &config.custom_variants[0];
+        assert_eq!(custom.repository_url, constants::GITHUB_URL);
+        assert_eq!(
+            custom.target_apps,
+            vec![App::Steam, App::HeroicGamesLauncher]
+        );
+
+        assert_eq!(
+            config
+                .custom_variants_for(&AppInstallations::SteamFlatpak)
+                .len(),
+            1
+        );
+        assert!(config
+            .custom_variants_for(&AppInstallations::HeroicWine)
+            .is_empty());
+        assert!(config
+            .custom_variants_for(&AppInstallations::Lutris)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let config = Config::parse(
+            r#"
+default_apps = ["steam", "bottles"]
+keep_versions = 3
+temp_dir = "/var/tmp/protonup"
+
+[preferred_variants]
+steam = "Luxtorpeda"
+lutris = "GEProton"
+
+[install_dirs]
+lutris_flatpak = "/mnt/games/runners/wine"
+heroic_wine = "/mnt/games/heroic/wine"
+"#,
+        );
+        assert!(config.is_ok(), "test: Config::parse returned error");
+        let config = config.unwrap();
+
+        assert!(config.is_default_app(App::Steam));
+        assert!(!config.is_default_app(App::Lutris));
+        assert_eq!(config.keep_versions, Some(3));
+        assert_eq!(config.temp_dir(), PathBuf::from("/var/tmp/protonup"));
+        assert_eq!(
+            config.install_dir(&AppInstallations::LutrisFlatpak),
+            PathBuf::from("/mnt/games/runners/wine")
+        );
+        assert_eq!(
+            config.install_dir(&AppInstallations::HeroicWine),
+            PathBuf::from("/mnt/games/heroic/wine")
+        );
+        // Heroic's Proton builds and other installations of the app keep their own directory
+        assert_eq!(
+            config.install_dir(&AppInstallations::HeroicProton),
+            AppInstallations::HeroicProton.default_install_dir()
+        );
+        assert_eq!(
+            config.install_dir(&AppInstallations::Lutris),
+            AppInstallations::Lutris.default_install_dir()
+        );
+        assert_eq!(
+            config
+                .preferred_variant(&AppInstallations::SteamFlatpak)
+                .to_string(),
+            "Luxtorpeda"
+        );
+        // GEProton can't be installed for Lutris, so its default is kept
+        assert_eq!(
+            config
+                .preferred_variant(&AppInstallations::Lutris)
+                .to_string(),
+            "WineGE"
+        );
+    }
+
+    #[test]
+    fn test_pinned_version() {
+        let config = Config::parse(
+            r#"
+[preferred_variants]
+steam = "Luxtorpeda"
+
+[pinned_versions]
+steam = "v68.0"
+lutris = "GE-Proton8-26"
+
+[pinned_variant_versions]
+GEProton = "GE-Proton8-25"
+WineGE = "GE-Proton8-20"
+"#,
+        );
+        assert!(config.is_ok(), "test: Config::parse returned error");
+        let config = config.unwrap();
+
+        let conditions = &[
+            (AppInstallations::Steam, Variant::Luxtorpeda, Some("v68.0")),
+            (
+                AppInstallations::Steam,
+                Variant::GEProton,
+                Some("GE-Proton8-25"),
+            ),
+            (
+                AppInstallations::HeroicProton,
+                Variant::GEProton,
+                Some("GE-Proton8-25"),
+            ),
+            (
+                AppInstallations::Lutris,
+                Variant::WineGE,
+                Some("GE-Proton8-26"),
+            ),
+            (
+                AppInstallations::Bottles,
+                Variant::WineGE,
+                Some("GE-Proton8-20"),
+            ),
+            (AppInstallations::Lutris, Variant::Kron4ekWine, None),
+        ];
+        for (app_inst, variant, expected) in conditions {
+            assert_eq!(
+                config.pinned_version(app_inst, variant),
+                *expected,
+                "case : '{} {}' test: pinned_version wrong",
+                app_inst,
+                variant
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_empty_config() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("custom_variants = 3").is_err());
+        assert!(Config::parse("keep_versions = 0").is_err());
+        assert!(Config::parse("[install_dirs]\nheroic = \"/tmp\"").is_err());
+    }
+}
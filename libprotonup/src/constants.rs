@@ -1,15 +1,35 @@
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-pub const TEMP_DIR: &str = "/tmp/";
-
 pub const GITHUB_URL: &str = "https://api.github.com/repos";
+/// The GitHub token is only sent to this host, custom variants can point to other servers
+pub const GITHUB_API_HOST: &str = "api.github.com";
 
 pub const GEPROTON_GITHUB_REPO: &str = "proton-ge-custom";
 pub const WINEGE_GITHUB_REPO: &str = "wine-ge-custom";
 pub const GE_GITHUB_ACCOUNT: &str = "GloriousEggroll";
 
+pub const LUXTORPEDA_GITHUB_REPO: &str = "luxtorpeda";
+pub const LUXTORPEDA_GITHUB_ACCOUNT: &str = "luxtorpeda-dev";
+
+pub const BOXTRON_GITHUB_REPO: &str = "boxtron";
+pub const BOXTRON_GITHUB_ACCOUNT: &str = "dreamer";
+
+pub const PROTONTKG_GITHUB_REPO: &str = "wine-tkg-git";
+pub const PROTONTKG_GITHUB_ACCOUNT: &str = "Frogging-Family";
+
+pub const KRON4EK_WINE_GITHUB_REPO: &str = "Wine-Builds";
+pub const KRON4EK_WINE_GITHUB_ACCOUNT: &str = "Kron4ek";
+
 pub const USER_AGENT: &str = "protoup-rs";
 
-// pub const CONFIG_FILE: &str = "~/.config/protonup/config.ini";
+// RELEASES_PER_PAGE is the largest page size the GitHub API allows
+pub const RELEASES_PER_PAGE: usize = 100;
+
+// CONFIG_FILE is relative to the XDG config directory
+pub const CONFIG_FILE: &str = "protonup/config.toml";
+
+// RELEASES_CACHE_DIR is relative to the XDG cache directory
+pub const RELEASES_CACHE_DIR: &str = "protonup/releases";
+
 // use const_format::formatcp;
 // pub const USER_AGENT: &'static str =  formatcp!("{}/v{}", USER_AGENT, VERSION);
@@ -1,19 +1,26 @@
 use super::constants;
-use crate::utils;
-use anyhow::{Context, Result};
+use crate::variants::AssetRules;
+use anyhow::{anyhow, Context, Result};
+use bzip2::read::BzDecoder;
 use flate2::read::GzDecoder;
 use futures_util::StreamExt;
 use reqwest::header::USER_AGENT;
 use sha2::{Digest, Sha512};
 use std::cmp::min;
-use std::fs::{File, OpenOptions};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
 use std::io;
-use std::io::Write;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tar::Archive;
+use tokio::sync::mpsc::{self, Receiver};
+use tokio::task;
 use xz2::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
 
 fn path_result(path: &Path) -> String {
     let spath = path.to_str();
@@ -23,58 +30,339 @@ fn path_result(path: &Path) -> String {
     }
 }
 
-// decompress will detect the extension and decompress the file with the appropriate function
-pub fn decompress(from_path: &Path, destination_path: &Path) -> Result<()> {
-    let path_str = from_path.as_os_str().to_string_lossy();
+/// Usual extensions of the archives `decompress` supports
+pub const ARCHIVE_EXTENSIONS: &[&str] = &["tar.gz", "tar.xz", "tar.zst", "tar.bz2", "tar"];
 
-    if path_str.ends_with("tar.gz") {
-        decompress_gz(from_path, destination_path)
-    } else if path_str.ends_with("tar.xz") {
-        decompress_xz(from_path, destination_path)
-    } else {
-        println!("no decompress\nPath: {:?}", from_path);
-        Ok(())
+/// Returns the archive extension a file name ends with, if it is one of ARCHIVE_EXTENSIONS
+pub fn archive_extension(file_name: &str) -> Option<&'static str> {
+    ARCHIVE_EXTENSIONS
+        .iter()
+        .find(|extension| {
+            file_name
+                .strip_suffix(*extension)
+                .is_some_and(|name| name.ends_with('.'))
+        })
+        .copied()
+}
+
+/// Formats of the archives `decompress` supports, tar archives compressed or not
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ArchiveFormat {
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+    Tar,
+}
+
+impl ArchiveFormat {
+    /// Detects the format from the first bytes of a file, its magic number.
+    /// Uncompressed tar archives are recognized by the "ustar" magic of their first header, at offset 257
+    pub fn from_magic(header: &[u8]) -> Option<ArchiveFormat> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveFormat::Gzip)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(ArchiveFormat::Xz)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(ArchiveFormat::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(ArchiveFormat::Bzip2)
+        } else if header.get(257..262) == Some(b"ustar") {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+
+    /// Detects the format of an archive file from its content, regardless of its extension
+    pub fn detect(path: &Path) -> Result<ArchiveFormat> {
+        let mut header = Vec::with_capacity(512);
+        open_archive(path)?
+            .take(512)
+            .read_to_end(&mut header)
+            .with_context(|| {
+                format!(
+                    "[Decompressing] Failed to read file from Path: {}",
+                    path_result(path),
+                )
+            })?;
+        ArchiveFormat::from_magic(&header).ok_or_else(|| unsupported_archive(path_result(path)))
     }
 }
 
-/// Decompress a tar.gz file
-fn decompress_gz(from_path: &Path, destination_path: &Path) -> Result<()> {
-    let file = File::open(from_path).with_context(|| {
-        format!(
-            "[Decompressing] Failed to open file from Path: {}",
-            path_result(from_path),
-        )
-    })?;
+fn unsupported_archive(name: String) -> anyhow::Error {
+    anyhow!(
+        "[Decompressing] Unsupported archive type, expected a tar archive, plain or compressed with gzip, xz, zstd or bzip2 : {}",
+        name
+    )
+}
 
-    let mut archive = Archive::new(GzDecoder::new(file));
+/// An archive entry that can't be installed safely, or an archive not holding a single version.
+/// Returned wrapped in the `anyhow::Error` of the unpacking functions, nothing of the archive is installed
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// An entry path going up with `..`, which could write outside the destination
+    EscapesDestination(PathBuf),
+    /// An entry with an absolute path
+    AbsolutePath(PathBuf),
+    /// A character or block device entry
+    DeviceNode(PathBuf),
+    /// A symlink or hard link whose target is outside the top-level directory
+    LinkEscapes { path: PathBuf, target: PathBuf },
+    /// An entry written through a symlink of the archive, which could point anywhere once followed
+    ThroughLink { path: PathBuf, link: PathBuf },
+    /// An entry next to the top-level directory, or a second top-level directory
+    NotSingleTopLevel(PathBuf),
+    /// The top-level directory isn't named after the release being installed
+    TopLevelMismatch { folder: String, expected: String },
+    /// The archive holds no entries
+    Empty,
+}
 
-    archive.unpack(destination_path).with_context(|| {
-        format!(
-            "[Decompressing] Failed to unpack into destination : {}",
-            path_result(destination_path)
-        )
-    })?;
-    Ok(())
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::EscapesDestination(path) => write!(
+                f,
+                "[Decompressing] Archive entry escapes the destination : {}",
+                path.display()
+            ),
+            Self::AbsolutePath(path) => write!(
+                f,
+                "[Decompressing] Archive entry has an absolute path : {}",
+                path.display()
+            ),
+            Self::DeviceNode(path) => write!(
+                f,
+                "[Decompressing] Archive entry is a device node : {}",
+                path.display()
+            ),
+            Self::LinkEscapes { path, target } => write!(
+                f,
+                "[Decompressing] Archive link points outside its top-level directory : {} -> {}",
+                path.display(),
+                target.display()
+            ),
+            Self::ThroughLink { path, link } => write!(
+                f,
+                "[Decompressing] Archive entry is written through the symlink {} : {}",
+                link.display(),
+                path.display()
+            ),
+            Self::NotSingleTopLevel(path) => write!(
+                f,
+                "[Decompressing] Archive must hold a single top-level directory, found : {}",
+                path.display()
+            ),
+            Self::TopLevelMismatch { folder, expected } => write!(
+                f,
+                "[Decompressing] Archive top-level directory {} doesn't match the release, expected {}",
+                folder, expected
+            ),
+            Self::Empty => write!(f, "[Decompressing] Archive is empty"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+/// What the archive of a release holds, checked and recorded when unpacking it, see `AssetRules::archive_release`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveRelease {
+    /// Release tag, recorded in the installed folder, see `INSTALLED_TAG_FILE`
+    pub tag: String,
+    /// Name of the archive's top-level folder, None when the variant's folder name isn't known
+    pub folder: Option<String>,
 }
 
-/// Decompress a tar.xz file
-fn decompress_xz(from_path: &Path, destination_path: &Path) -> Result<()> {
-    let file = File::open(from_path).with_context(|| {
+/// File recorded in installed folders, holding the release tag. Variants with a fixed folder name are only known by it
+pub const INSTALLED_TAG_FILE: &str = ".protonup-tag";
+
+// decompress will detect the archive format from its content and decompress the file with the appropriate decoder.
+// The archive is unpacked into a staging directory and moved into place once complete, see `unpack_verified`
+pub fn decompress(
+    from_path: &Path,
+    destination_path: &Path,
+    release: Option<&ArchiveRelease>,
+) -> Result<()> {
+    // Detect the format first, reporting unsupported files with their path
+    ArchiveFormat::detect(from_path)?;
+    unpack_verified(open_archive(from_path)?, None, release, destination_path)
+}
+
+/// Unpacks a tar archive, decompressing it according to its format. Returns the archive's top-level directory, see `unpack`
+fn unpack_format<R: Read>(
+    format: ArchiveFormat,
+    reader: R,
+    expected_folder: Option<&str>,
+    destination_path: &Path,
+) -> Result<String> {
+    match format {
+        ArchiveFormat::Gzip => unpack(
+            Archive::new(GzDecoder::new(reader)),
+            expected_folder,
+            destination_path,
+        ),
+        ArchiveFormat::Xz => unpack(
+            Archive::new(XzDecoder::new(reader)),
+            expected_folder,
+            destination_path,
+        ),
+        ArchiveFormat::Zstd => {
+            let decoder =
+                ZstdDecoder::new(reader).context("[Decompressing] Failed to read zstd stream")?;
+            unpack(Archive::new(decoder), expected_folder, destination_path)
+        }
+        ArchiveFormat::Bzip2 => unpack(
+            Archive::new(BzDecoder::new(reader)),
+            expected_folder,
+            destination_path,
+        ),
+        ArchiveFormat::Tar => unpack(Archive::new(reader), expected_folder, destination_path),
+    }
+}
+
+fn open_archive(from_path: &Path) -> Result<File> {
+    File::open(from_path).with_context(|| {
         format!(
             "[Decompressing] Failed to open file from Path: {}",
             path_result(from_path),
         )
-    })?;
-
-    let mut archive = Archive::new(XzDecoder::new(file));
+    })
+}
 
-    archive.unpack(destination_path).with_context(|| {
+/// Unpacks the entries one by one, each one is validated with `check_entry` before being written.
+/// The archive must hold a single top-level directory, named `expected_folder` when it is set, its name is returned
+fn unpack<R: Read>(
+    mut archive: Archive<R>,
+    expected_folder: Option<&str>,
+    destination_path: &Path,
+) -> Result<String> {
+    let unpack_error = || {
         format!(
             "[Decompressing] Failed to unpack into destination : {}",
             path_result(destination_path)
         )
-    })?;
-    Ok(())
+    };
+    let mut top_level: Option<String> = None;
+    let mut symlinks = HashSet::new();
+    for entry in archive.entries().with_context(unpack_error)? {
+        let mut entry = entry.with_context(unpack_error)?;
+        let path = entry.path().with_context(unpack_error)?.into_owned();
+        let link = entry.link_name().with_context(unpack_error)?;
+        let entry_type = entry.header().entry_type();
+        let folder = check_entry(&path, entry_type, link.as_deref(), &symlinks)?;
+        let Some(folder) = folder else {
+            continue;
+        };
+        match &top_level {
+            Some(top_level) if *top_level != folder => {
+                return Err(ArchiveError::NotSingleTopLevel(path).into())
+            }
+            Some(_) => {}
+            None => {
+                if let Some(expected) = expected_folder {
+                    if folder != expected {
+                        return Err(ArchiveError::TopLevelMismatch {
+                            folder,
+                            expected: expected.to_owned(),
+                        }
+                        .into());
+                    }
+                }
+                top_level = Some(folder);
+            }
+        }
+        if entry_type.is_symlink() {
+            symlinks.insert(relative_components(&path)?.iter().collect::<PathBuf>());
+        }
+        entry
+            .unpack_in(destination_path)
+            .with_context(unpack_error)?;
+    }
+    top_level.ok_or_else(|| ArchiveError::Empty.into())
+}
+
+/// Checks an archive entry can be unpacked without writing outside the destination, returning its top-level directory.
+/// None for the entries that aren't files, the archive root like "./" and the pax global headers added by `git archive`.
+/// `symlinks` holds the paths of the symlinks unpacked before, no entry can be written through them
+fn check_entry(
+    path: &Path,
+    entry_type: tar::EntryType,
+    link: Option<&Path>,
+    symlinks: &HashSet<PathBuf>,
+) -> Result<Option<String>, ArchiveError> {
+    if entry_type.is_pax_global_extensions() {
+        return Ok(None);
+    }
+    let components = relative_components(path)?;
+    if entry_type.is_character_special() || entry_type.is_block_special() {
+        return Err(ArchiveError::DeviceNode(path.to_path_buf()));
+    }
+    let Some(folder) = components.first() else {
+        return Ok(None);
+    };
+    if components.len() == 1 && !entry_type.is_dir() {
+        return Err(ArchiveError::NotSingleTopLevel(path.to_path_buf()));
+    }
+    let mut parent = PathBuf::new();
+    for component in &components[..components.len() - 1] {
+        parent.push(component);
+        if symlinks.contains(&parent) {
+            return Err(ArchiveError::ThroughLink {
+                path: path.to_path_buf(),
+                link: parent,
+            });
+        }
+    }
+    if let Some(target) = link {
+        let link_escapes = || ArchiveError::LinkEscapes {
+            path: path.to_path_buf(),
+            target: target.to_path_buf(),
+        };
+        if entry_type.is_symlink() {
+            // Symlink targets are relative to the directory of the link, they must stay inside the top-level directory.
+            // Only the leading `..` are allowed, the ones after a name could go up from wherever another symlink points
+            let mut depth = components.len() - 1;
+            let mut descending = false;
+            for component in target.components() {
+                match component {
+                    Component::Normal(_) => {
+                        depth += 1;
+                        descending = true;
+                    }
+                    Component::ParentDir if depth > 1 && !descending => depth -= 1,
+                    Component::CurDir => {}
+                    _ => return Err(link_escapes()),
+                }
+            }
+        } else if entry_type.is_hard_link() {
+            // Hard link targets are other entries of the top-level directory
+            let target_components = relative_components(target).map_err(|_| link_escapes())?;
+            if target_components.first() != Some(folder) {
+                return Err(link_escapes());
+            }
+        }
+    }
+    Ok(Some(folder.as_os_str().to_string_lossy().to_string()))
+}
+
+/// Returns the components of an entry path, rejecting absolute paths and the ones going up with `..`
+fn relative_components(path: &Path) -> Result<Vec<Component<'_>>, ArchiveError> {
+    let mut components = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => components.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(ArchiveError::EscapesDestination(path.to_path_buf()))
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(ArchiveError::AbsolutePath(path.to_path_buf()))
+            }
+        }
+    }
+    Ok(components)
 }
 
 /// Creates the progress trackers variable pointers
@@ -86,21 +374,23 @@ pub fn create_progress_trackers() -> (Arc<AtomicUsize>, Arc<AtomicBool>) {
 }
 
 // check_if_exists checks if a folder exists in a path
-pub fn check_if_exists(path: &str, tag: &str) -> bool {
-    let f_path = utils::expand_tilde(format!("{path}{tag}/")).unwrap();
-    let p = f_path.as_path();
-    p.is_dir()
+pub fn check_if_exists(path: &Path, tag: &str) -> bool {
+    path.join(tag).is_dir()
 }
 
 // list_folders_in_path returns a vector of strings of the folders in a path
-pub fn list_folders_in_path(path: &str) -> Result<Vec<String>, anyhow::Error> {
-    let f_path = utils::expand_tilde(path).unwrap();
-    let p = f_path.as_path();
-    let paths: Vec<String> = p
+pub fn list_folders_in_path(path: &Path) -> Result<Vec<String>, anyhow::Error> {
+    let paths: Vec<String> = path
         .read_dir()
-        .with_context(|| format!("Failed to read directory : {}", path_result(p)))?
+        .with_context(|| format!("Failed to read directory : {}", path_result(path)))?
         .filter_map(|e| e.ok())
         .filter(|e| e.path().is_dir())
+        // staging directories hold unfinished installs
+        .filter(|e| {
+            !e.file_name()
+                .to_string_lossy()
+                .starts_with(STAGING_DIR_PREFIX)
+        })
         .map(|e| {
             let path = e.path();
             let name = path.file_name().unwrap();
@@ -111,14 +401,72 @@ pub fn list_folders_in_path(path: &str) -> Result<Vec<String>, anyhow::Error> {
 }
 
 // removes a directory and all its contents
-pub fn remove_dir_all(path: &str) -> Result<()> {
-    let f_path = utils::expand_tilde(path).unwrap();
-    let p = f_path.as_path();
-    std::fs::remove_dir_all(p)
-        .with_context(|| format!("[Remove] Failed to remove directory : {}", path_result(p)))?;
+pub fn remove_dir_all(path: &Path) -> Result<()> {
+    std::fs::remove_dir_all(path).with_context(|| {
+        format!(
+            "[Remove] Failed to remove directory : {}",
+            path_result(path)
+        )
+    })?;
     Ok(())
 }
 
+/// Returns the tag recorded in an installed folder, see `INSTALLED_TAG_FILE`
+pub fn installed_tag(folder_path: &Path) -> Option<String> {
+    fs::read_to_string(folder_path.join(INSTALLED_TAG_FILE))
+        .ok()
+        .map(|tag| tag.trim().to_owned())
+}
+
+/// Returns the folders of an install directory holding releases of a variant, with the tag of the release each one holds.
+/// Folders are matched by the variant's folder name, see `AssetRules::folder_release`, folders of other releases are left out
+pub fn installed_releases(
+    install_dir: &Path,
+    rules: &AssetRules,
+    release_tags: &[String],
+) -> Result<Vec<(String, String)>> {
+    Ok(list_folders_in_path(install_dir)?
+        .into_iter()
+        .filter_map(|folder| {
+            let recorded_tag = installed_tag(&install_dir.join(&folder));
+            let release = rules.folder_release(&folder, recorded_tag.as_deref(), release_tags)?;
+            Some((folder, release_tags[release].clone()))
+        })
+        .collect())
+}
+
+/// Removes the installed versions of a variant beyond the `keep` newest releases.
+/// `release_tags` must be sorted from newest to oldest, folders not holding any of them are left untouched.
+/// Each folder holds a single release, see `installed_releases`. Returns the names of the removed folders
+pub fn prune_old_versions(
+    install_dir: &Path,
+    rules: &AssetRules,
+    release_tags: &[String],
+    keep: usize,
+) -> Result<Vec<String>> {
+    let mut installed: Vec<(usize, String)> = installed_releases(install_dir, rules, release_tags)?
+        .into_iter()
+        .filter_map(|(folder, tag)| {
+            let release = release_tags.iter().position(|release| *release == tag)?;
+            Some((release, folder))
+        })
+        .collect();
+    installed.sort();
+    let mut releases: Vec<usize> = installed.iter().map(|(release, _)| *release).collect();
+    releases.dedup();
+    let kept = &releases[..keep.min(releases.len())];
+
+    let mut removed = vec![];
+    for (_, folder) in installed
+        .into_iter()
+        .filter(|(release, _)| !kept.contains(release))
+    {
+        remove_dir_all(&install_dir.join(&folder))?;
+        removed.push(folder);
+    }
+    Ok(removed)
+}
+
 /// requires pointers to store the progress, and another to store "done" status
 /// Create them with `create_progress_trackers`
 pub async fn download_file_progress(
@@ -168,6 +516,351 @@ pub async fn download_file_progress(
     Ok(())
 }
 
+/// Prefix of the hidden staging directories archives are unpacked into, before being moved into their install directory
+pub const STAGING_DIR_PREFIX: &str = ".protonup-staging-";
+
+/// Directory of the staging directory the versions being replaced are moved into
+const REPLACED_DIR: &str = ".protonup-replaced";
+
+/// Lock file of an install directory, locked while installing into it, see `lock_install_dir`
+const INSTALL_LOCK_FILE: &str = ".protonup-lock";
+
+/// Number of downloaded chunks waiting to be unpacked before the download waits for the unpacking
+const UNPACK_QUEUE_CHUNKS: usize = 64;
+
+/// Downloads an archive and unpacks it while it is being downloaded, without storing it. See `unpack_verified`.
+/// requires pointers to store the progress, and another to store "done" status
+/// Create them with `create_progress_trackers`
+pub async fn download_and_unpack(
+    url: String,
+    total_size: u64,
+    git_hash: Option<String>,
+    release: Option<ArchiveRelease>,
+    destination_path: &Path,
+    progress: Arc<AtomicUsize>,
+    done: Arc<AtomicBool>,
+) -> Result<()> {
+    let client = reqwest::Client::new();
+    let res = client
+        .get(&url)
+        .header(USER_AGENT, format!("protonup-rs {}", constants::VERSION))
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .with_context(|| format!("[Download] Failed to call remote server on URL : {}", &url))?;
+
+    // Unpacking is blocking work, it runs on the blocking thread pool fed with the downloaded chunks
+    let (sender, receiver) = mpsc::channel(UNPACK_QUEUE_CHUNKS);
+    let destination = destination_path.to_path_buf();
+    let unpacker = task::spawn_blocking(move || {
+        unpack_verified(
+            ChannelReader::new(receiver),
+            git_hash.as_deref(),
+            release.as_ref(),
+            &destination,
+        )
+    });
+
+    let mut downloaded: u64 = 0;
+    let mut stream = res.bytes_stream();
+    let mut download_error = None;
+
+    while let Some(item) = stream.next().await {
+        let chunk = match item {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                // Fail the unpacking too, a truncated archive must not be installed
+                let _ = sender.send(Err(io::Error::other(e.to_string()))).await;
+                download_error =
+                    Some(anyhow!(e).context("[Download] Failed reading stream from network"));
+                break;
+            }
+        };
+        let new = min(downloaded + (chunk.len() as u64), total_size);
+        downloaded = new;
+        progress.swap(new as usize, Ordering::SeqCst);
+        // The unpacking stopped early, its error is returned below
+        if sender.send(Ok(chunk.to_vec())).await.is_err() {
+            break;
+        }
+    }
+    drop(sender);
+    done.swap(true, Ordering::SeqCst);
+
+    let unpacked = unpacker
+        .await
+        .map_err(|e| anyhow!("[Decompressing] The unpacking task failed : {}", e))?;
+    match download_error {
+        Some(e) => Err(e),
+        None => unpacked,
+    }
+}
+
+/// Reads an archive, plain or compressed, and unpacks it into a staging directory inside `destination_path`, hashing it on the way.
+/// The unpacked files are only moved into `destination_path` if the archive's SHA-512 matches `git_hash`, the content of its sha512sum file,
+/// and if it holds a single top-level directory, named like the folder of `release` when it is known. Unsafe entries are rejected, see `ArchiveError`.
+/// The tag of `release` is recorded in the installed folder.
+/// The staging directory is removed in every case, and the ones left by interrupted installs are removed first
+pub fn unpack_verified<R: Read>(
+    reader: R,
+    git_hash: Option<&str>,
+    release: Option<&ArchiveRelease>,
+    destination_path: &Path,
+) -> Result<()> {
+    // Installs into the same directory run one after the other, the staging directories found are leftovers
+    let _lock = lock_install_dir(destination_path)?;
+    // Leftovers only waste space, failing to remove them doesn't prevent installing
+    let _ = remove_leftovers(destination_path);
+    let staging = destination_path.join(format!("{}{}", STAGING_DIR_PREFIX, process::id()));
+    let result = unpack_staged(reader, git_hash, release, &staging, destination_path);
+    if staging.exists() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    result
+}
+
+fn unpack_staged<R: Read>(
+    reader: R,
+    git_hash: Option<&str>,
+    release: Option<&ArchiveRelease>,
+    staging: &Path,
+    destination_path: &Path,
+) -> Result<()> {
+    fs::create_dir_all(staging).with_context(|| {
+        format!(
+            "[Decompressing] Failed creating staging directory : {}",
+            path_result(staging)
+        )
+    })?;
+    let expected_folder = release.and_then(|release| release.folder.as_deref());
+    let (hash, folder) = unpack_stream(reader, expected_folder, staging)?;
+    if let Some(git_hash) = git_hash {
+        if hash != expected_hash(git_hash)? {
+            return Err(anyhow!(
+                "[Hash Check] The archive doesn't match its sha512sum, nothing was installed"
+            ));
+        }
+    }
+    if let Some(release) = release {
+        let tag_file = staging.join(&folder).join(INSTALLED_TAG_FILE);
+        // The archive could hold a link by that name, it is replaced rather than written through
+        let _ = fs::remove_file(&tag_file);
+        fs::write(&tag_file, &release.tag).with_context(|| {
+            format!(
+                "[Decompressing] Failed recording the installed release : {}",
+                path_result(&tag_file)
+            )
+        })?;
+    }
+    commit_staged(staging, &folder, destination_path)
+}
+
+/// Unpacks an archive read from a stream, returning the hex encoded SHA-512 of the whole stream and the archive's top-level directory
+fn unpack_stream<R: Read>(
+    reader: R,
+    expected_folder: Option<&str>,
+    destination_path: &Path,
+) -> Result<(String, String)> {
+    let mut reader = HashingReader {
+        inner: reader,
+        hasher: Sha512::new(),
+    };
+    let mut header = Vec::with_capacity(512);
+    (&mut reader)
+        .take(512)
+        .read_to_end(&mut header)
+        .context("[Decompressing] Failed reading archive")?;
+    let format = ArchiveFormat::from_magic(&header)
+        .ok_or_else(|| unsupported_archive("downloaded archive".to_owned()))?;
+    let folder = unpack_format(
+        format,
+        io::Cursor::new(header).chain(&mut reader),
+        expected_folder,
+        destination_path,
+    )?;
+    // The archive can end before the stream, like the zero blocks after the end of a tar archive, they are hashed too
+    io::copy(&mut reader, &mut io::sink()).context("[Decompressing] Failed reading archive")?;
+    Ok((hex::encode(reader.hasher.finalize()), folder))
+}
+
+/// Moves the unpacked top-level directory from the staging directory into the destination with a single rename.
+/// The version it replaces is first moved aside into the staging directory, to be removed with it,
+/// so an interrupted install never leaves a partially written version in the destination.
+/// It is moved back if the new version can't be moved into place, see `remove_staging_leftovers` for interrupted installs
+fn commit_staged(staging: &Path, folder: &str, destination_path: &Path) -> Result<()> {
+    let target = destination_path.join(folder);
+    let replaced = staging.join(REPLACED_DIR).join(folder);
+    // symlink_metadata also finds broken symlinks
+    let replacing = target.symlink_metadata().is_ok();
+    if replacing {
+        fs::create_dir_all(staging.join(REPLACED_DIR))
+            .and_then(|_| fs::rename(&target, &replaced))
+            .with_context(|| {
+                format!(
+                    "[Decompressing] Failed moving aside the version being replaced : {}",
+                    path_result(&target)
+                )
+            })?;
+    }
+    fs::rename(staging.join(folder), &target).or_else(|e| {
+        if replacing {
+            fs::rename(&replaced, &target).with_context(|| {
+                format!(
+                    "[Decompressing] Failed restoring the replaced version, it was kept in : {}",
+                    path_result(&replaced)
+                )
+            })?;
+        }
+        Err(anyhow!(e)).with_context(|| {
+            format!(
+                "[Decompressing] Failed moving unpacked files into destination : {}",
+                path_result(&target)
+            )
+        })
+    })
+}
+
+/// Opens the lock file of an install directory, creating both if needed
+fn open_install_lock(install_dir: &Path) -> Result<File> {
+    fs::create_dir_all(install_dir).with_context(|| {
+        format!(
+            "[Decompressing] Failed creating install directory : {}",
+            path_result(install_dir)
+        )
+    })?;
+    let path = install_dir.join(INSTALL_LOCK_FILE);
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| {
+            format!(
+                "[Decompressing] Failed opening the install lock : {}",
+                path_result(&path)
+            )
+        })
+}
+
+/// Locks an install directory for the duration of an install, waiting for the one running in it, from this process or another, to finish.
+/// The lock is released when the returned file is dropped, or when the process exits
+fn lock_install_dir(install_dir: &Path) -> Result<File> {
+    let lock = open_install_lock(install_dir)?;
+    lock.lock().with_context(|| {
+        format!(
+            "[Decompressing] Failed locking the install directory : {}",
+            path_result(install_dir)
+        )
+    })?;
+    Ok(lock)
+}
+
+/// Removes the staging directories left in an install directory by interrupted installs, returning their names.
+/// Nothing is removed while an install is running in it, see `lock_install_dir`.
+/// A version moved aside by an install interrupted before its replacement was moved into place is restored first
+pub fn remove_staging_leftovers(install_dir: &Path) -> Result<Vec<String>> {
+    let lock = open_install_lock(install_dir)?;
+    match lock.try_lock() {
+        Ok(()) => remove_leftovers(install_dir),
+        Err(TryLockError::WouldBlock) => Ok(vec![]),
+        Err(TryLockError::Error(e)) => Err(e).with_context(|| {
+            format!(
+                "[Decompressing] Failed locking the install directory : {}",
+                path_result(install_dir)
+            )
+        }),
+    }
+}
+
+/// Removes the staging directories of an install directory, which must be locked
+fn remove_leftovers(install_dir: &Path) -> Result<Vec<String>> {
+    let mut removed = vec![];
+    let entries = install_dir
+        .read_dir()
+        .with_context(|| format!("Failed to read directory : {}", path_result(install_dir)))?;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(STAGING_DIR_PREFIX) {
+            restore_replaced(&entry.path(), install_dir)?;
+            remove_dir_all(&entry.path())?;
+            removed.push(name);
+        }
+    }
+    Ok(removed)
+}
+
+/// Moves the versions set aside in a staging directory back into the install directory, unless they were replaced
+fn restore_replaced(staging: &Path, install_dir: &Path) -> Result<()> {
+    let replaced = staging.join(REPLACED_DIR);
+    if !replaced.is_dir() {
+        return Ok(());
+    }
+    let entries = replaced
+        .read_dir()
+        .with_context(|| format!("Failed to read directory : {}", path_result(&replaced)))?;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let target = install_dir.join(entry.file_name());
+        if target.symlink_metadata().is_err() {
+            fs::rename(entry.path(), &target).with_context(|| {
+                format!(
+                    "[Decompressing] Failed restoring the replaced version : {}",
+                    path_result(&target)
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Reader hashing everything read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha512,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Reader over the chunks of a download, sent by the task downloading it. Ends when the sender is dropped
+struct ChannelReader {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl ChannelReader {
+    fn new(chunks: Receiver<io::Result<Vec<u8>>>) -> ChannelReader {
+        ChannelReader {
+            chunks,
+            chunk: vec![],
+            position: 0,
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.chunk.len() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk?;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let read = min(buf.len(), self.chunk.len() - self.position);
+        buf[..read].copy_from_slice(&self.chunk[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
 pub async fn download_file_into_memory(url: &String) -> Result<String> {
     let client = reqwest::Client::new();
     let res = client
@@ -196,12 +889,587 @@ pub fn hash_check_file(file_dir: String, git_hash: String) -> Result<bool> {
 
     let hash = hasher.finalize();
 
+    Ok(hex::encode(hash) == expected_hash(&git_hash)?)
+}
+
+/// Returns the hash from the content of a sha512sum file, `<hash>  <file name>`
+fn expected_hash(git_hash: &str) -> Result<&str> {
     let (git_hash, _) = git_hash
         .rsplit_once(' ')
         .context("[Hash Check] Failed decoding hash file. Is this the right hash ? Please file an issue to protonup-rs !")?;
+    Ok(git_hash.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variants::Variant;
+
+    /// Compresses a tar archive
+    type Encoder = fn(Vec<u8>) -> Vec<u8>;
+
+    /// Returns the names of the entries of an install directory, without its lock file
+    fn install_dir_entries(dir: &Path) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name != INSTALL_LOCK_FILE)
+            .collect()
+    }
+
+    /// Returns the release held by `tar_archive`
+    fn ge_proton_release() -> ArchiveRelease {
+        Variant::GEProton
+            .asset_rules()
+            .archive_release("GE-Proton8-25")
+    }
+
+    /// Returns a tar archive holding `GE-Proton8-25/proton`
+    fn tar_archive() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let content = b"#!/usr/bin/env python3";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "GE-Proton8-25/proton", &content[..])
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    /// Writes a tar archive holding `GE-Proton8-25/proton`, compressed by `encode`
+    fn write_archive(path: &Path, encode: Encoder) {
+        fs::write(path, encode(tar_archive())).unwrap();
+    }
+
+    fn gzip(tar: Vec<u8>) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&tar).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress() {
+        let dir = std::env::temp_dir().join(format!("protonup-rs-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let conditions: &[(&str, Encoder)] = &[
+            ("GE-Proton8-25.tar", |tar| tar),
+            ("GE-Proton8-25.tar.gz", gzip),
+            ("GE-Proton8-25.tar.xz", |tar| {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(&tar).unwrap();
+                encoder.finish().unwrap()
+            }),
+            ("GE-Proton8-25.tar.zst", |tar| {
+                zstd::stream::encode_all(&tar[..], 0).unwrap()
+            }),
+            ("GE-Proton8-25.tar.bz2", |tar| {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(&tar).unwrap();
+                encoder.finish().unwrap()
+            }),
+            // the format is detected from the content, not from the extension
+            ("GE-Proton8-25.tar.xz.download", gzip),
+        ];
+
+        for (name, encode) in conditions {
+            let archive = dir.join(name);
+            let destination = dir.join(format!("{}-out", name));
+            write_archive(&archive, *encode);
+            let result = decompress(&archive, &destination, Some(&ge_proton_release()));
+            assert!(
+                result.is_ok(),
+                "case : '{}' test: decompress returned error {:?}",
+                name,
+                result
+            );
+            assert!(
+                destination.join("GE-Proton8-25/proton").is_file(),
+                "case : '{}' test: decompress didn't unpack the archive",
+                name
+            );
+        }
+
+        let unknown = dir.join("GE-Proton8-25.tar.gz");
+        fs::write(&unknown, b"PK\x03\x04").unwrap();
+        assert!(
+            decompress(&unknown, &dir.join("zip-out"), None).is_err(),
+            "test: decompress accepted an unknown archive type"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_verified() {
+        let dir = std::env::temp_dir().join(format!("protonup-rs-unpack-{}", process::id()));
+        let archive = gzip(tar_archive());
+        let hash = format!(
+            "{}  GE-Proton8-25.tar.gz",
+            hex::encode(Sha512::digest(&archive))
+        );
+        let wrong_hash = format!("{}  GE-Proton8-25.tar.gz", "0".repeat(128));
+
+        let conditions = &[
+            ("matching hash", Some(hash.as_str()), true),
+            ("no hash", None, true),
+            ("wrong hash", Some(wrong_hash.as_str()), false),
+        ];
+        for (desc, git_hash, expected) in conditions {
+            let destination = dir.join(desc.replace(' ', "-"));
+            fs::create_dir_all(&destination).unwrap();
+            // stream the archive in small chunks, like a download
+            let (sender, receiver) = mpsc::channel(4);
+            let chunks = archive.clone();
+            let streamer = std::thread::spawn(move || {
+                for chunk in chunks.chunks(100) {
+                    sender.blocking_send(Ok(chunk.to_vec())).unwrap();
+                }
+            });
+            let result = unpack_verified(
+                ChannelReader::new(receiver),
+                *git_hash,
+                Some(&ge_proton_release()),
+                &destination,
+            );
+            streamer.join().unwrap();
+
+            assert_eq!(
+                result.is_ok(),
+                *expected,
+                "case : '{}' test: unpack_verified wrong result {:?}",
+                desc,
+                result
+            );
+            assert_eq!(
+                destination.join("GE-Proton8-25/proton").is_file(),
+                *expected,
+                "case : '{}' test: unpack_verified installed wrong",
+                desc
+            );
+            assert_eq!(
+                installed_tag(&destination.join("GE-Proton8-25")),
+                expected.then(|| "GE-Proton8-25".to_owned()),
+                "case : '{}' test: unpack_verified recorded the wrong tag",
+                desc
+            );
+            assert_eq!(
+                install_dir_entries(&destination).len(),
+                *expected as usize,
+                "case : '{}' test: unpack_verified left a staging directory",
+                desc
+            );
+        }
+
+        // a download failing midway must not install a truncated archive
+        let destination = dir.join("failed-download");
+        fs::create_dir_all(&destination).unwrap();
+        let (sender, receiver) = mpsc::channel(4);
+        sender.blocking_send(Ok(archive[..100].to_vec())).unwrap();
+        sender
+            .blocking_send(Err(io::Error::other("connection reset")))
+            .unwrap();
+        drop(sender);
+        assert!(
+            unpack_verified(ChannelReader::new(receiver), None, None, &destination).is_err(),
+            "test: unpack_verified installed a failed download"
+        );
+        assert_eq!(install_dir_entries(&destination).len(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Appends an entry without the path checks of `tar::Builder`, to build unsafe archives
+    fn append_raw(
+        builder: &mut tar::Builder<Vec<u8>>,
+        path: &str,
+        entry_type: tar::EntryType,
+        link: Option<&str>,
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        if let Some(link) = link {
+            header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+        }
+        header.set_entry_type(entry_type);
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append(&header, io::empty()).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_verified_rejects_unsafe_archives() {
+        use tar::EntryType::{Char, Directory, Link, Regular, Symlink};
+
+        let dir = std::env::temp_dir().join(format!("protonup-rs-unsafe-{}", process::id()));
+        type Entries<'a> = &'a [(&'a str, tar::EntryType, Option<&'a str>)];
+        let conditions: &[(&str, Entries, Option<ArchiveError>)] = &[
+            (
+                "valid",
+                &[
+                    ("GE-Proton8-25/", Directory, None),
+                    ("GE-Proton8-25/proton", Regular, None),
+                    ("GE-Proton8-25/files/proton", Symlink, Some("../proton")),
+                    (
+                        "GE-Proton8-25/proton-link",
+                        Link,
+                        Some("GE-Proton8-25/proton"),
+                    ),
+                ],
+                None,
+            ),
+            (
+                "dot prefix",
+                &[
+                    ("./", Directory, None),
+                    ("./GE-Proton8-25/proton", Regular, None),
+                ],
+                None,
+            ),
+            (
+                "parent dir",
+                &[("GE-Proton8-25/../../evil", Regular, None)],
+                Some(ArchiveError::EscapesDestination(
+                    "GE-Proton8-25/../../evil".into(),
+                )),
+            ),
+            (
+                "absolute path",
+                &[("/tmp/evil", Regular, None)],
+                Some(ArchiveError::AbsolutePath("/tmp/evil".into())),
+            ),
+            (
+                "device node",
+                &[("GE-Proton8-25/null", Char, None)],
+                Some(ArchiveError::DeviceNode("GE-Proton8-25/null".into())),
+            ),
+            (
+                "symlink outside",
+                &[("GE-Proton8-25/etc", Symlink, Some("../../etc"))],
+                Some(ArchiveError::LinkEscapes {
+                    path: "GE-Proton8-25/etc".into(),
+                    target: "../../etc".into(),
+                }),
+            ),
+            (
+                "absolute symlink",
+                &[("GE-Proton8-25/etc", Symlink, Some("/etc"))],
+                Some(ArchiveError::LinkEscapes {
+                    path: "GE-Proton8-25/etc".into(),
+                    target: "/etc".into(),
+                }),
+            ),
+            (
+                "hard link outside",
+                &[("GE-Proton8-25/passwd", Link, Some("../etc/passwd"))],
+                Some(ArchiveError::LinkEscapes {
+                    path: "GE-Proton8-25/passwd".into(),
+                    target: "../etc/passwd".into(),
+                }),
+            ),
+            (
+                "two top-level directories",
+                &[
+                    ("GE-Proton8-25/proton", Regular, None),
+                    ("GE-Proton8-24/proton", Regular, None),
+                ],
+                Some(ArchiveError::NotSingleTopLevel(
+                    "GE-Proton8-24/proton".into(),
+                )),
+            ),
+            (
+                "top-level file",
+                &[("proton", Regular, None)],
+                Some(ArchiveError::NotSingleTopLevel("proton".into())),
+            ),
+            (
+                "other release",
+                &[("GE-Proton8-24/proton", Regular, None)],
+                Some(ArchiveError::TopLevelMismatch {
+                    folder: "GE-Proton8-24".to_owned(),
+                    expected: "GE-Proton8-25".to_owned(),
+                }),
+            ),
+            (
+                "release with a suffix",
+                &[("GE-Proton8-25-custom/proton", Regular, None)],
+                Some(ArchiveError::TopLevelMismatch {
+                    folder: "GE-Proton8-25-custom".to_owned(),
+                    expected: "GE-Proton8-25".to_owned(),
+                }),
+            ),
+            (
+                "symlink to the top-level parent",
+                &[
+                    ("GE-Proton8-25/link", Symlink, Some("..")),
+                    ("GE-Proton8-25/link/evil", Symlink, Some("../..")),
+                    ("GE-Proton8-25/link/GE-Proton8-24/", Directory, None),
+                ],
+                Some(ArchiveError::LinkEscapes {
+                    path: "GE-Proton8-25/link".into(),
+                    target: "..".into(),
+                }),
+            ),
+            (
+                "entry through a symlink",
+                &[
+                    ("GE-Proton8-25/files/", Directory, None),
+                    ("GE-Proton8-25/link", Symlink, Some("files")),
+                    ("GE-Proton8-25/link/proton", Regular, None),
+                ],
+                Some(ArchiveError::ThroughLink {
+                    path: "GE-Proton8-25/link/proton".into(),
+                    link: "GE-Proton8-25/link".into(),
+                }),
+            ),
+            (
+                "parent dir after a symlink",
+                &[
+                    ("GE-Proton8-25/files/", Directory, None),
+                    ("GE-Proton8-25/files/up", Symlink, Some("..")),
+                    ("GE-Proton8-25/evil", Symlink, Some("files/up/../..")),
+                ],
+                Some(ArchiveError::LinkEscapes {
+                    path: "GE-Proton8-25/evil".into(),
+                    target: "files/up/../..".into(),
+                }),
+            ),
+            (
+                "hard link to another folder",
+                &[
+                    ("GE-Proton8-25/proton", Regular, None),
+                    ("GE-Proton8-25/other", Link, Some("GE-Proton8-24/proton")),
+                ],
+                Some(ArchiveError::LinkEscapes {
+                    path: "GE-Proton8-25/other".into(),
+                    target: "GE-Proton8-24/proton".into(),
+                }),
+            ),
+            ("empty", &[], Some(ArchiveError::Empty)),
+        ];
+
+        for (desc, entries, expected) in conditions {
+            let mut builder = tar::Builder::new(Vec::new());
+            // an install of another version, which must be left alone
+            fs::create_dir_all(dir.join(desc.replace(' ', "-")).join("GE-Proton8-24")).unwrap();
+            for (path, entry_type, link) in *entries {
+                append_raw(&mut builder, path, *entry_type, *link);
+            }
+            let archive = gzip(builder.into_inner().unwrap());
+            let destination = dir.join(desc.replace(' ', "-"));
+            fs::create_dir_all(&destination).unwrap();
+
+            let result =
+                unpack_verified(&archive[..], None, Some(&ge_proton_release()), &destination);
+            assert_eq!(
+                result
+                    .as_ref()
+                    .err()
+                    .and_then(|e| e.downcast_ref::<ArchiveError>()),
+                expected.as_ref(),
+                "case : '{}' test: unpack_verified wrong result {:?}",
+                desc,
+                result
+            );
+            if expected.is_some() {
+                assert_eq!(
+                    install_dir_entries(&destination),
+                    vec!["GE-Proton8-24"],
+                    "case : '{}' test: unpack_verified installed an unsafe archive",
+                    desc
+                );
+            } else {
+                assert!(
+                    destination.join("GE-Proton8-25/proton").is_file(),
+                    "case : '{}' test: unpack_verified didn't unpack the archive",
+                    desc
+                );
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_verified_replaces_atomically() {
+        let dir = std::env::temp_dir().join(format!("protonup-rs-replace-{}", process::id()));
+        let old_file = dir.join("GE-Proton8-25/old-file");
+        fs::create_dir_all(old_file.parent().unwrap()).unwrap();
+        fs::write(&old_file, b"old").unwrap();
+        // left by an interrupted install
+        let leftover = dir.join(format!("{}{}", STAGING_DIR_PREFIX, u32::MAX));
+        fs::create_dir_all(leftover.join("GE-Proton8-24")).unwrap();
+        // a version moved aside by an install interrupted before moving its replacement into place
+        let interrupted = dir.join(format!("{}{}", STAGING_DIR_PREFIX, u32::MAX - 1));
+        fs::create_dir_all(interrupted.join(REPLACED_DIR).join("GE-Proton8-23")).unwrap();
+
+        // the staging directories of an install still running are kept
+        let running = lock_install_dir(&dir).unwrap();
+        assert_eq!(
+            remove_staging_leftovers(&dir).unwrap(),
+            Vec::<String>::new(),
+            "test: remove_staging_leftovers removed the staging directories of a running install"
+        );
+        assert!(leftover.exists() && interrupted.exists());
+        drop(running);
+
+        let result = unpack_verified(&gzip(tar_archive())[..], None, None, &dir);
+        assert!(
+            result.is_ok(),
+            "test: unpack_verified returned error {:?}",
+            result
+        );
+        assert!(dir.join("GE-Proton8-25/proton").is_file());
+        assert!(
+            !old_file.exists(),
+            "test: unpack_verified kept files of the replaced version"
+        );
+        assert!(!leftover.exists(), "test: staging leftover not removed");
+        assert!(!interrupted.exists(), "test: staging leftover not removed");
+        let mut folders = list_folders_in_path(&dir).unwrap();
+        folders.sort();
+        assert_eq!(
+            folders,
+            vec!["GE-Proton8-23".to_string(), "GE-Proton8-25".to_string()],
+            "test: the interrupted replacement wasn't restored"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_commit_staged_restores_replaced() {
+        let dir = std::env::temp_dir().join(format!("protonup-rs-commit-{}", process::id()));
+        let staging = dir.join(format!("{}{}", STAGING_DIR_PREFIX, process::id()));
+        fs::create_dir_all(&staging).unwrap();
+        let old_file = dir.join("GE-Proton8-25/old-file");
+        fs::create_dir_all(old_file.parent().unwrap()).unwrap();
+        fs::write(&old_file, b"old").unwrap();
+
+        // the unpacked version is missing from the staging directory, it can't be moved into place
+        assert!(
+            commit_staged(&staging, "GE-Proton8-25", &dir).is_err(),
+            "test: commit_staged succeeded without an unpacked version"
+        );
+        assert!(
+            old_file.is_file(),
+            "test: commit_staged didn't restore the replaced version"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_prune_old_versions() {
+        let tags: Vec<String> = ["9.0", "9.0-rc5", "8.0.1", "8.0"]
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect();
+        let folder = |tag: &str| format!("wine-{}-staging-tkg-amd64", tag);
+        let conditions: &[(&str, &[&str], usize, &[&str])] = &[
+            ("point release", &["8.0.1", "8.0"], 1, &["8.0.1"]),
+            ("release candidate", &["9.0-rc5", "9.0"], 1, &["9.0"]),
+            (
+                "keep two",
+                &["9.0", "9.0-rc5", "8.0.1", "8.0"],
+                2,
+                &["9.0", "9.0-rc5"],
+            ),
+            ("keep more than installed", &["8.0"], 3, &["8.0"]),
+        ];
+
+        for (desc, installed, keep, expected) in conditions {
+            let dir = std::env::temp_dir().join(format!(
+                "protonup-rs-prune-{}-{}",
+                process::id(),
+                desc.replace(' ', "-")
+            ));
+            for tag in *installed {
+                fs::create_dir_all(dir.join(folder(tag))).unwrap();
+            }
+            // not a release of the variant, left untouched
+            fs::create_dir_all(dir.join("GE-Proton8-25")).unwrap();
+
+            let result =
+                prune_old_versions(&dir, &Variant::Kron4ekWine.asset_rules(), &tags, *keep);
+            assert!(
+                result.is_ok(),
+                "case : '{}' test: prune_old_versions returned error {:?}",
+                desc,
+                result
+            );
+            let mut folders = list_folders_in_path(&dir).unwrap();
+            folders.sort();
+            let mut expected: Vec<String> = expected.iter().map(|tag| folder(tag)).collect();
+            expected.push("GE-Proton8-25".to_string());
+            expected.sort();
+            assert_eq!(
+                folders, expected,
+                "case : '{}' test: prune_old_versions kept the wrong versions",
+                desc
+            );
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_archive_format_from_magic() {
+        let mut tar_header = vec![0u8; 512];
+        tar_header[257..262].copy_from_slice(b"ustar");
+        let conditions: &[(&str, &[u8], Option<ArchiveFormat>)] = &[
+            ("gzip", &[0x1f, 0x8b, 0x08, 0x00], Some(ArchiveFormat::Gzip)),
+            (
+                "xz",
+                &[0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00],
+                Some(ArchiveFormat::Xz),
+            ),
+            (
+                "zstd",
+                &[0x28, 0xb5, 0x2f, 0xfd, 0x04],
+                Some(ArchiveFormat::Zstd),
+            ),
+            ("bzip2", b"BZh91AY&SY", Some(ArchiveFormat::Bzip2)),
+            ("tar", &tar_header, Some(ArchiveFormat::Tar)),
+            ("zip", b"PK\x03\x04", None),
+            ("short xz", &[0xfd, b'7', b'z'], None),
+            ("empty", &[], None),
+        ];
+
+        for (desc, header, expected) in conditions {
+            assert_eq!(
+                ArchiveFormat::from_magic(header),
+                *expected,
+                "case : '{}' test: ArchiveFormat::from_magic wrong",
+                desc
+            );
+        }
+    }
+
+    #[test]
+    fn test_archive_extension() {
+        let conditions = &[
+            ("GE-Proton8-25.tar.gz", Some("tar.gz")),
+            ("proton_tkg_8.16.tar.zst", Some("tar.zst")),
+            ("wine-9.0-amd64.tar.xz", Some("tar.xz")),
+            ("boxtron.tar", Some("tar")),
+            ("wine.tar.bz2", Some("tar.bz2")),
+            ("GE-Proton8-25.sha512sum", None),
+            ("GE-Proton8-25.zip", None),
+            ("guitar", None),
+        ];
 
-    if hex::encode(hash) != git_hash.trim() {
-        return Ok(false);
+        for (name, expected) in conditions {
+            assert_eq!(
+                archive_extension(name),
+                *expected,
+                "case : '{}' test: archive_extension wrong",
+                name
+            );
+        }
     }
-    Ok(true)
 }
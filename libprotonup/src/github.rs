@@ -1,10 +1,82 @@
-use crate::constants;
-use crate::variants::VariantGithubParameters;
+use crate::variants::{Variant, VariantGithubParameters};
+use crate::{constants, paths};
 use anyhow::Result;
+use reqwest::header::{HeaderMap, AUTHORIZATION, ETAG, IF_NONE_MATCH, LINK};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub type ReleaseList = Vec<Release>;
 
+/// Errors of the GitHub API calls
+#[derive(Debug)]
+pub enum GithubError {
+    /// The API rate limit was reached. `reset` is the Unix time requests are allowed again at, from `X-RateLimit-Reset`
+    RateLimited { reset: Option<u64> },
+    /// The request failed, or its response couldn't be read
+    Request(reqwest::Error),
+}
+
+impl GithubError {
+    /// Returns true if the API answered that the requested resource doesn't exist
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            GithubError::Request(e) => e.status() == Some(StatusCode::NOT_FOUND),
+            GithubError::RateLimited { .. } => false,
+        }
+    }
+
+    /// Returns true if the API couldn't be used at all, because of the network or the rate limit
+    fn is_unreachable(&self) -> bool {
+        match self {
+            GithubError::Request(e) => e.is_connect() || e.is_timeout(),
+            GithubError::RateLimited { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for GithubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GithubError::RateLimited { reset: Some(reset) } => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|now| now.as_secs())
+                    .unwrap_or_default();
+                write!(
+                    f,
+                    "GitHub API rate limit exceeded, it resets in {} minute(s) (at Unix time {}). Set GITHUB_TOKEN to raise the limit",
+                    reset.saturating_sub(now).div_ceil(60),
+                    reset
+                )
+            }
+            GithubError::RateLimited { reset: None } => write!(
+                f,
+                "GitHub API rate limit exceeded. Set GITHUB_TOKEN to raise the limit"
+            ),
+            GithubError::Request(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for GithubError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GithubError::Request(e) => Some(e),
+            GithubError::RateLimited { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for GithubError {
+    fn from(e: reqwest::Error) -> GithubError {
+        GithubError::Request(e)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Release {
     /// API URL of the Release
@@ -24,16 +96,25 @@ impl std::fmt::Display for Release {
 }
 
 impl Release {
-    /// Returns a Download struct corresponding to the Release
-    pub fn get_download_info(&self) -> Download {
-        let mut download: Download = Download::default();
-        download.version = self.tag_name.clone();
+    /// Returns a Download struct corresponding to the Release.
+    /// The archive and sha512sum are picked with the Variant's AssetRules, the sha512sum URL is left empty when the Release has none
+    pub fn get_download_info(&self, variant: &Variant) -> Download {
+        let rules = variant.asset_rules();
+        let mut download = Download {
+            version: self.tag_name.clone(),
+            ..Default::default()
+        };
+        let mut archive_preference = usize::MAX;
         for asset in &self.assets {
-            if asset.name.ends_with("sha512sum") {
+            if rules.is_checksum(&asset.name) {
                 download.sha512sum_url = asset.browser_download_url.clone();
-            } else if asset.name.ends_with("tar.gz") || asset.name.ends_with("tar.xz") {
-                download.download_url = asset.browser_download_url.clone();
-                download.size = asset.size as u64;
+            } else if let Some(preference) = rules.archive_preference(&asset.name) {
+                // Keep the first asset matching the most preferred pattern
+                if preference < archive_preference {
+                    archive_preference = preference;
+                    download.download_url = asset.browser_download_url.clone();
+                    download.size = asset.size as u64;
+                }
             }
         }
 
@@ -56,25 +137,246 @@ pub struct Asset {
     browser_download_url: String,
 }
 
-/// Returns a Vec of Releases from a GitHub repository, the URL used for the request is built from the passed in VariantParameters
+/// Returns a Vec of Releases from a GitHub repository, newest first, the URL used for the request is built from the passed in VariantParameters.
+/// Follows the pages of the listing until `limit` Releases are found, or until the last page when `limit` is None.
+/// `token` is a GitHub API token, anonymous requests have a much lower rate limit.
+/// Pages are cached on disk, they are only downloaded again when they changed, and used as is when GitHub can't be reached
 pub async fn list_releases(
     source: &VariantGithubParameters,
-) -> Result<ReleaseList, reqwest::Error> {
+    limit: Option<usize>,
+    token: Option<&str>,
+) -> Result<ReleaseList, GithubError> {
+    let cache_dir = paths::cache_home().join(constants::RELEASES_CACHE_DIR);
+    list_releases_cached(source, limit, token, &cache_dir).await
+}
+
+/// Same as `list_releases`, caching the pages in `cache_dir`
+async fn list_releases_cached(
+    source: &VariantGithubParameters,
+    limit: Option<usize>,
+    token: Option<&str>,
+    cache_dir: &Path,
+) -> Result<ReleaseList, GithubError> {
+    let per_page = limit
+        .unwrap_or(constants::RELEASES_PER_PAGE)
+        .clamp(1, constants::RELEASES_PER_PAGE);
+    let mut url = format!("{}?per_page={}", releases_url(source), per_page);
+
+    let client = client()?;
+
+    let mut r_list = ReleaseList::new();
+    loop {
+        let page = get_page(&client, &url, token, cache_dir).await?;
+        let next_url = page.next_url;
+        r_list.extend(page.releases);
+
+        if let Some(limit) = limit {
+            if r_list.len() >= limit {
+                r_list.truncate(limit);
+                break;
+            }
+        }
+        match next_url {
+            Some(next_url) => url = next_url,
+            None => break,
+        }
+    }
+
+    Ok(r_list)
+}
+
+/// Returns the Release with the given tag from a GitHub repository.
+/// Fails with a 404 status error when the repository has no such Release
+pub async fn get_release_by_tag(
+    source: &VariantGithubParameters,
+    tag: &str,
+    token: Option<&str>,
+) -> Result<Release, GithubError> {
+    get_release(release_tag_url(source, tag), token).await
+}
+
+/// Returns the latest Release of a GitHub repository, as marked by GitHub. Drafts and pre-releases are skipped
+pub async fn get_latest_release(
+    source: &VariantGithubParameters,
+    token: Option<&str>,
+) -> Result<Release, GithubError> {
+    get_release(format!("{}/latest", releases_url(source)), token).await
+}
+
+async fn get_release(url: String, token: Option<&str>) -> Result<Release, GithubError> {
+    Ok(send(client()?.get(url), token).await?.json().await?)
+}
+
+/// A page of a release listing, as stored in the cache
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedPage {
+    /// ETag of the page, sent back to GitHub to know if it changed
+    etag: String,
+    /// URL of the next page, from the `Link` header
+    next_url: Option<String>,
+    releases: ReleaseList,
+}
+
+/// Returns a page of a release listing, from the cache in `cache_dir` when GitHub answers it didn't change or can't be reached
+async fn get_page(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+    cache_dir: &Path,
+) -> Result<CachedPage, GithubError> {
+    let cache_file = cache_dir.join(cache_file_name(url));
+    let cached = read_cached_page(&cache_file);
+
+    let mut request = client.get(url);
+    if let Some(cached) = &cached {
+        request = request.header(IF_NONE_MATCH, &cached.etag);
+    }
+    let res = match (send(request, token).await, cached) {
+        (Ok(res), Some(cached)) if res.status() == StatusCode::NOT_MODIFIED => return Ok(cached),
+        (Ok(res), _) => res,
+        (Err(e), Some(cached)) if e.is_unreachable() => return Ok(cached),
+        (Err(e), _) => return Err(e),
+    };
+
+    let etag = res
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_owned);
+    let next_url = res
+        .headers()
+        .get(LINK)
+        .and_then(|link| link.to_str().ok())
+        .and_then(next_page_url);
+    let releases: ReleaseList = res.json().await?;
+    let page = CachedPage {
+        etag: etag.unwrap_or_default(),
+        next_url,
+        releases,
+    };
+    // The cache only saves requests, failing to write it is not an error
+    if !page.etag.is_empty() {
+        let _ = write_cached_page(&cache_file, &page);
+    }
+    Ok(page)
+}
+
+/// Returns the name of the cache file of a URL, keeping only its alphanumeric characters and dashes
+fn cache_file_name(url: &str) -> String {
+    let name: String = url
+        .trim_start_matches("https://")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.json", name)
+}
+
+fn read_cached_page(path: &Path) -> Option<CachedPage> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn write_cached_page(path: &Path, page: &CachedPage) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(page)?)?;
+    Ok(())
+}
+
+fn client() -> Result<reqwest::Client, GithubError> {
     let agent = format!("{}/v{}", constants::USER_AGENT, constants::VERSION,);
 
-    let url = format!(
+    Ok(reqwest::Client::builder().user_agent(agent).build()?)
+}
+
+/// Sends a request to the API, authenticated with the token if any and if the request goes to GitHub.
+/// Fails on rate limit and error statuses, whose bodies aren't the expected JSON
+async fn send(
+    request: reqwest::RequestBuilder,
+    token: Option<&str>,
+) -> Result<reqwest::Response, GithubError> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let send_token = is_github_api(request.url());
+    let mut request = reqwest::RequestBuilder::from_parts(client, request);
+    if let Some(token) = token.filter(|_| send_token) {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let res = request.send().await?;
+    if let Some(e) = rate_limit_error(res.status(), res.headers()) {
+        return Err(e);
+    }
+    Ok(res.error_for_status()?)
+}
+
+/// Returns true for the URLs of the GitHub API, the only ones the token is sent to
+fn is_github_api(url: &reqwest::Url) -> bool {
+    url.scheme() == "https" && url.host_str() == Some(constants::GITHUB_API_HOST)
+}
+
+/// Returns a RateLimited error if the response status and headers say the rate limit was reached.
+/// GitHub answers 429, or 403 with no remaining requests
+fn rate_limit_error(status: StatusCode, headers: &HeaderMap) -> Option<GithubError> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    let limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN && header("x-ratelimit-remaining") == Some(0));
+    limited.then(|| GithubError::RateLimited {
+        reset: header("x-ratelimit-reset"),
+    })
+}
+
+/// Returns the API URL of the releases of a repository
+fn releases_url(source: &VariantGithubParameters) -> String {
+    format!(
         "{}/{}/{}/releases",
         source.repository_url, source.repository_account, source.repository_name,
-    );
-
-    let client = reqwest::Client::builder().user_agent(agent).build()?;
+    )
+}
 
-    let r_list: ReleaseList = client.get(url).send().await?.json().await?;
+/// Returns the URL of the Release with the given tag. The tag is percent-encoded as a single path segment,
+/// a tag like "../../user" can't point the request, and the token sent with it, elsewhere
+fn release_tag_url(source: &VariantGithubParameters, tag: &str) -> String {
+    let url = format!("{}/tags", releases_url(source));
+    // An invalid repository URL is reported when sending the request
+    match reqwest::Url::parse(&url) {
+        Ok(mut parsed) => {
+            if let Ok(mut segments) = parsed.path_segments_mut() {
+                segments.push(tag);
+            }
+            parsed.to_string()
+        }
+        Err(_) => url,
+    }
+}
 
-    Ok(r_list)
+/// Returns the URL of the next page from a `Link` header, like `<https://api.github.com/...?page=2>; rel="next", <...>; rel="last"`
+fn next_page_url(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_owned()
+            })
+    })
 }
 
-#[derive(Default, Debug, PartialEq, Clone)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize)]
 pub struct Download {
     /// Proton or Wine GE version, based off tag
     pub version: String,
@@ -90,6 +392,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_list_releases() {
+        // The tests calling GitHub don't write into the user's cache
+        let cache_dir =
+            std::env::temp_dir().join(format!("protonup-rs-cache-{}", std::process::id()));
         let conditions = &[
             (
                 variants::Variant::WineGE.get_github_parameters(),
@@ -102,7 +407,7 @@ mod tests {
         ];
 
         for (source_parameters, desc) in conditions {
-            let result = list_releases(source_parameters).await;
+            let result = list_releases_cached(source_parameters, None, None, &cache_dir).await;
 
             assert!(
                 result.is_ok(),
@@ -120,20 +425,242 @@ mod tests {
                 desc
             );
         }
+        let _ = fs::remove_dir_all(&cache_dir);
     }
 
-    #[tokio::test]
-    async fn test_get_release() {
-        let agent = format!("{}/v{}", constants::USER_AGENT, constants::VERSION,);
+    #[test]
+    fn test_next_page_url() {
+        let conditions = &[
+            (
+                r#"<https://api.github.com/repositories/1/releases?per_page=100&page=2>; rel="next", <https://api.github.com/repositories/1/releases?per_page=100&page=3>; rel="last""#,
+                Some("https://api.github.com/repositories/1/releases?per_page=100&page=2"),
+            ),
+            (
+                r#"<https://api.github.com/repositories/1/releases?per_page=100&page=2>; rel="prev", <https://api.github.com/repositories/1/releases?per_page=100&page=1>; rel="first""#,
+                None,
+            ),
+            ("", None),
+        ];
 
-        let client = match reqwest::Client::builder().user_agent(agent).build() {
-            Ok(client) => client,
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                std::process::exit(1)
-            }
+        for (link, expected) in conditions {
+            assert_eq!(
+                next_page_url(link).as_deref(),
+                *expected,
+                "case : '{}' test: next_page_url wrong",
+                link
+            );
+        }
+    }
+
+    #[test]
+    fn test_release_tag_url() {
+        let source = Variant::GEProton.get_github_parameters();
+        let conditions = &[
+            (
+                "GE-Proton8-25",
+                "https://api.github.com/repos/GloriousEggroll/proton-ge-custom/releases/tags/GE-Proton8-25",
+            ),
+            (
+                "../../user",
+                "https://api.github.com/repos/GloriousEggroll/proton-ge-custom/releases/tags/..%2F..%2Fuser",
+            ),
+            // dot segments are dropped, the request stays within the releases
+            (
+                "..",
+                "https://api.github.com/repos/GloriousEggroll/proton-ge-custom/releases/tags",
+            ),
+            (
+                "8.0?page=2#top",
+                "https://api.github.com/repos/GloriousEggroll/proton-ge-custom/releases/tags/8.0%3Fpage=2%23top",
+            ),
+        ];
+
+        for (tag, expected) in conditions {
+            assert_eq!(
+                release_tag_url(&source, tag),
+                *expected,
+                "case : '{}' test: release_tag_url wrong",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_github_api() {
+        let conditions = &[
+            (
+                "https://api.github.com/repos/GloriousEggroll/proton-ge-custom/releases",
+                true,
+            ),
+            ("http://api.github.com/repos/a/b/releases", false),
+            ("https://gitea.example.com/api/v1/repos/a/b/releases", false),
+            (
+                "https://api.github.com.example.com/repos/a/b/releases",
+                false,
+            ),
+        ];
+
+        for (url, expected) in conditions {
+            assert_eq!(
+                is_github_api(&url.parse().unwrap()),
+                *expected,
+                "case : '{}' test: is_github_api wrong",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_error() {
+        let headers = |remaining: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-ratelimit-remaining", remaining.parse().unwrap());
+            headers.insert("x-ratelimit-reset", "1700000000".parse().unwrap());
+            headers
         };
+        let conditions = &[
+            (StatusCode::FORBIDDEN, headers("0"), Some(1700000000)),
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                headers("12"),
+                Some(1700000000),
+            ),
+            (StatusCode::FORBIDDEN, headers("12"), None),
+            (StatusCode::OK, headers("0"), None),
+        ];
+
+        for (status, headers, expected) in conditions {
+            let reset = match rate_limit_error(*status, headers) {
+                Some(GithubError::RateLimited { reset }) => reset,
+                _ => None,
+            };
+            assert_eq!(
+                reset, *expected,
+                "case : '{}' test: rate_limit_error wrong",
+                status
+            );
+        }
+        assert!(
+            rate_limit_error(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new()).is_some(),
+            "test: rate_limit_error without headers wrong"
+        );
+    }
+
+    #[test]
+    fn test_cached_page() {
+        let url =
+            "https://api.github.com/repos/GloriousEggroll/proton-ge-custom/releases?per_page=100";
+        assert_eq!(
+            cache_file_name(url),
+            "api_github_com_repos_GloriousEggroll_proton-ge-custom_releases_per_page_100.json"
+        );
+
+        let path = std::env::temp_dir()
+            .join(format!("protonup-rs-test-{}", std::process::id()))
+            .join(cache_file_name(url));
+        let page = CachedPage {
+            etag: r#"W/"0123456789abcdef""#.to_owned(),
+            next_url: Some(format!("{}&page=2", url)),
+            releases: vec![release_with_assets(
+                "GE-Proton8-25",
+                &["GE-Proton8-25.sha512sum", "GE-Proton8-25.tar.gz"],
+            )],
+        };
+        assert!(
+            write_cached_page(&path, &page).is_ok(),
+            "test: write_cached_page returned error"
+        );
+        let cached = read_cached_page(&path);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
 
+        let cached = cached.expect("test: read_cached_page returned nothing");
+        assert_eq!(cached.etag, page.etag);
+        assert_eq!(cached.next_url, page.next_url);
+        assert_eq!(
+            cached.releases[0].get_download_info(&variants::Variant::GEProton),
+            page.releases[0].get_download_info(&variants::Variant::GEProton)
+        );
+        assert!(read_cached_page(&path).is_none());
+    }
+
+    fn release_with_assets(tag_name: &str, asset_names: &[&str]) -> Release {
+        Release {
+            url: None,
+            tag_name: tag_name.to_owned(),
+            name: tag_name.to_owned(),
+            assets: asset_names
+                .iter()
+                .enumerate()
+                .map(|(id, name)| Asset {
+                    url: String::new(),
+                    id: id as i64,
+                    name: name.to_string(),
+                    size: 100,
+                    updated_at: String::new(),
+                    browser_download_url: format!("https://example.com/{name}"),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_get_download_info() {
+        let conditions = &[
+            (
+                variants::Variant::GEProton,
+                release_with_assets(
+                    "GE-Proton8-25",
+                    &["GE-Proton8-25.sha512sum", "GE-Proton8-25.tar.gz"],
+                ),
+                "https://example.com/GE-Proton8-25.tar.gz",
+                "https://example.com/GE-Proton8-25.sha512sum",
+            ),
+            (
+                variants::Variant::Kron4ekWine,
+                release_with_assets(
+                    "9.0",
+                    &[
+                        "wine-9.0-amd64.tar.xz",
+                        "wine-9.0-staging-tkg-amd64-wow64.tar.xz",
+                        "wine-9.0-staging-tkg-amd64.tar.xz",
+                        "wine-9.0-staging-tkg-x86.tar.xz",
+                    ],
+                ),
+                "https://example.com/wine-9.0-staging-tkg-amd64.tar.xz",
+                "",
+            ),
+            (
+                variants::Variant::ProtonTkg,
+                release_with_assets(
+                    "8.16.r4.g77d5bee7",
+                    &[
+                        "proton_tkg_8.16.r4.g77d5bee7.tar.zst",
+                        "wine-tkg-staging-fsync-git-8.16.r4.g77d5bee7-1-x86_64.pkg.tar.zst",
+                        "proton_tkg_8.16.r4.g77d5bee7.tar.xz",
+                    ],
+                ),
+                "https://example.com/proton_tkg_8.16.r4.g77d5bee7.tar.xz",
+                "",
+            ),
+        ];
+
+        for (variant, release, download_url, sha512sum_url) in conditions {
+            let download = release.get_download_info(variant);
+            assert_eq!(
+                &download.download_url, download_url,
+                "case : '{}' test: get_download_info picked the wrong archive",
+                variant
+            );
+            assert_eq!(
+                &download.sha512sum_url, sha512sum_url,
+                "case : '{}' test: get_download_info picked the wrong sha512sum",
+                variant
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_release() {
         let conditions = &[
             (
                 variants::Variant::WineGE.get_github_parameters(),
@@ -145,21 +672,7 @@ mod tests {
             ),
         ];
         for (source_parameters, desc) in conditions {
-            let url = format!(
-                "{}/{}/{}/releases/latest",
-                source_parameters.repository_url,
-                source_parameters.repository_account,
-                source_parameters.repository_name
-            );
-
-            let rel = match client.get(url).send().await {
-                Ok(res) => res,
-                Err(e) => {
-                    panic!("Error: {}", e);
-                }
-            }
-            .json::<Release>()
-            .await;
+            let rel = get_latest_release(source_parameters, None).await;
 
             println!("Got result: {rel:?}");
 
@@ -168,6 +681,14 @@ mod tests {
                 "case : '{}' test: test_get_release wrong",
                 desc
             );
+
+            let tag = rel.unwrap().tag_name;
+            let rel = get_release_by_tag(source_parameters, &tag, None).await;
+            assert!(
+                rel.is_ok_and(|rel| rel.tag_name == tag),
+                "case : '{}' test: get_release_by_tag wrong",
+                desc
+            );
         }
     }
 }
@@ -1,6 +1,10 @@
 pub mod apps;
+pub mod config;
 pub mod constants;
 pub mod files;
 pub mod github;
+pub mod lutris;
+pub mod paths;
+pub mod steam;
 pub mod utils;
 pub mod variants;
@@ -0,0 +1,96 @@
+use crate::{paths, utils};
+use std::fs;
+use std::path::PathBuf;
+
+/// Name of the Lutris settings file, an INI file that lives in the Lutris config or data directory
+pub const LUTRIS_CONF_FILE: &str = "lutris.conf";
+
+/// Flatpak app ID of Lutris
+pub const LUTRIS_FLATPAK_ID: &str = "net.lutris.Lutris";
+
+/// Returns the Lutris data directory, where runners are kept by default
+pub fn data_dir(flatpak: bool) -> PathBuf {
+    if flatpak {
+        paths::flatpak_app_dir(LUTRIS_FLATPAK_ID).join("data/lutris")
+    } else {
+        paths::data_home().join("lutris")
+    }
+}
+
+/// Returns the directories that may contain lutris.conf.
+/// Older Lutris versions keep it in the config directory, newer ones in the data directory
+fn conf_dirs(flatpak: bool) -> Vec<PathBuf> {
+    let config_dir = if flatpak {
+        paths::flatpak_app_dir(LUTRIS_FLATPAK_ID).join("config/lutris")
+    } else {
+        paths::config_home().join("lutris")
+    };
+    vec![config_dir, data_dir(flatpak)]
+}
+
+/// Reads the `runner_dir` option from the `[lutris]` section of a lutris.conf file content
+pub fn runner_dir_from_conf(content: &str) -> Option<PathBuf> {
+    let mut in_lutris_section = false;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') && line.ends_with(']') {
+            in_lutris_section = line[1..line.len() - 1].trim() == "lutris";
+            continue;
+        }
+        if !in_lutris_section {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim();
+            if key.trim() == "runner_dir" && !value.is_empty() {
+                return utils::expand_tilde(value);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the directory Lutris keeps its runners in.
+/// A `runner_dir` configured in lutris.conf takes precedence over `<data dir>/runners`
+pub fn runners_dir(flatpak: bool) -> PathBuf {
+    conf_dirs(flatpak)
+        .iter()
+        .filter_map(|dir| fs::read_to_string(dir.join(LUTRIS_CONF_FILE)).ok())
+        .find_map(|content| runner_dir_from_conf(&content))
+        .unwrap_or_else(|| data_dir(flatpak).join("runners"))
+}
+
+/// Returns the directory Lutris looks for Wine builds in
+pub fn wine_runners_dir(flatpak: bool) -> PathBuf {
+    runners_dir(flatpak).join("wine")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runner_dir_from_conf() {
+        let conditions = &[
+            (
+                "[lutris]\nwidth = 800\nrunner_dir = /mnt/games/runners\n",
+                Some(PathBuf::from("/mnt/games/runners")),
+                "configured runner_dir",
+            ),
+            (
+                "[services]\nrunner_dir = /mnt/games/runners\n[lutris]\nwidth = 800\n",
+                None,
+                "runner_dir in another section",
+            ),
+            ("[lutris]\nrunner_dir = \n", None, "empty runner_dir"),
+        ];
+
+        for (input, expected, desc) in conditions {
+            assert_eq!(
+                &runner_dir_from_conf(input),
+                expected,
+                "case : '{}' test: runner_dir_from_conf wrong",
+                desc
+            );
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Returns the directory from `env_var` when it is set to an absolute path, as required by the XDG spec
+fn env_dir(env_var: &str) -> Option<PathBuf> {
+    env::var_os(env_var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+/// Returns the user's home directory, from `$HOME`
+pub fn home_dir() -> PathBuf {
+    env_dir("HOME")
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("/"))
+}
+
+/// Returns `$XDG_DATA_HOME`, or `~/.local/share` when unset
+pub fn data_home() -> PathBuf {
+    env_dir("XDG_DATA_HOME").unwrap_or_else(|| home_dir().join(".local/share"))
+}
+
+/// Returns `$XDG_CONFIG_HOME`, or `~/.config` when unset
+pub fn config_home() -> PathBuf {
+    env_dir("XDG_CONFIG_HOME").unwrap_or_else(|| home_dir().join(".config"))
+}
+
+/// Returns `$XDG_CACHE_HOME`, or `~/.cache` when unset
+pub fn cache_home() -> PathBuf {
+    env_dir("XDG_CACHE_HOME").unwrap_or_else(|| home_dir().join(".cache"))
+}
+
+/// Returns `$TMPDIR`, or `/tmp` when unset. Archives installed into several directories are stored here until unpacked
+pub fn temp_dir() -> PathBuf {
+    env_dir("TMPDIR").unwrap_or_else(|| PathBuf::from("/tmp"))
+}
+
+/// Returns the base directory of a Flatpak app's sandbox, `~/.var/app/<app_id>`.
+/// Flatpak relocates the XDG directories of the app under it, regardless of the host's variables
+pub fn flatpak_app_dir(app_id: &str) -> PathBuf {
+    home_dir().join(".var/app").join(app_id)
+}
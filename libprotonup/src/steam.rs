@@ -0,0 +1,245 @@
+use crate::paths;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the locations where a native Steam installation may live, in order of preference.
+/// `~/.steam/root` and `~/.steam/steam` are usually symlinks created by the Steam client, and may be dangling.
+pub fn steam_root_candidates() -> Vec<PathBuf> {
+    let home = paths::home_dir();
+    vec![
+        home.join(".steam/root"),
+        home.join(".steam/steam"),
+        paths::data_home().join("Steam"),
+    ]
+}
+
+/// File listing all Steam library folders, relative to the Steam root
+pub const LIBRARY_FOLDERS_FILE: &str = "config/libraryfolders.vdf";
+
+/// A node of a Valve KeyValues (VDF) document, either a plain value or a nested table of key/value pairs
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Vdf {
+    Value(String),
+    Table(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    /// Returns the first child with the given key, ignoring case like Steam does
+    pub fn get(&self, key: &str) -> Option<&Vdf> {
+        match self {
+            Vdf::Table(entries) => entries
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v),
+            Vdf::Value(_) => None,
+        }
+    }
+
+    /// Returns the string of a Value node
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Vdf::Value(value) => Some(value),
+            Vdf::Table(_) => None,
+        }
+    }
+}
+
+/// Parses the text of a VDF document into its root table
+pub fn parse_vdf(input: &str) -> Result<Vdf> {
+    let tokens = tokenize_vdf(input)?;
+    let mut tokens = tokens.into_iter();
+    let root = parse_vdf_table(&mut tokens, false)?;
+    Ok(root)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum VdfToken {
+    String(String),
+    Open,
+    Close,
+}
+
+fn tokenize_vdf(input: &str) -> Result<Vec<VdfToken>> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(VdfToken::Open),
+            '}' => tokens.push(VdfToken::Close),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(escaped) => value.push(escaped),
+                            None => bail!("[VDF] Unexpected end of file inside escape sequence"),
+                        },
+                        Some(c) => value.push(c),
+                        None => bail!("[VDF] Unterminated string"),
+                    }
+                }
+                tokens.push(VdfToken::String(value));
+            }
+            // Comments run until the end of the line
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            // Unquoted keys and values are allowed, they end at whitespace or braces
+            c => {
+                let mut value = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '{' || next == '}' || next == '"' {
+                        break;
+                    }
+                    value.push(next);
+                    chars.next();
+                }
+                tokens.push(VdfToken::String(value));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_vdf_table(tokens: &mut impl Iterator<Item = VdfToken>, nested: bool) -> Result<Vdf> {
+    let mut entries = vec![];
+    loop {
+        let key = match tokens.next() {
+            Some(VdfToken::String(key)) => key,
+            Some(VdfToken::Close) if nested => return Ok(Vdf::Table(entries)),
+            None if !nested => return Ok(Vdf::Table(entries)),
+            Some(token) => bail!("[VDF] Expected a key, found {:?}", token),
+            None => bail!("[VDF] Unexpected end of file, missing a closing brace"),
+        };
+        let value = match tokens.next() {
+            Some(VdfToken::String(value)) => Vdf::Value(value),
+            Some(VdfToken::Open) => parse_vdf_table(tokens, true)?,
+            Some(VdfToken::Close) | None => bail!("[VDF] Key \"{}\" has no value", key),
+        };
+        entries.push((key, value));
+    }
+}
+
+/// Returns the paths listed in a libraryfolders.vdf document.
+/// Supports both the current format, where every library is a table with a "path" key,
+/// and the legacy format where numbered keys map directly to a path
+pub fn library_folders_from_vdf(vdf: &Vdf) -> Vec<PathBuf> {
+    let libraries = match vdf.get("libraryfolders") {
+        Some(Vdf::Table(entries)) => entries,
+        _ => return vec![],
+    };
+    libraries
+        .iter()
+        // Only numbered keys are libraries, others are metadata like "contentstatsid"
+        .filter(|(key, _)| key.parse::<u32>().is_ok())
+        .filter_map(|(_, library)| match library {
+            Vdf::Value(path) => Some(PathBuf::from(path)),
+            Vdf::Table(_) => library.get("path").and_then(Vdf::as_str).map(PathBuf::from),
+        })
+        .collect()
+}
+
+/// Reads and parses the libraryfolders.vdf file of a Steam root
+pub fn library_folders(steam_root: &Path) -> Result<Vec<PathBuf>> {
+    let vdf_path = steam_root.join(LIBRARY_FOLDERS_FILE);
+    let content = fs::read_to_string(&vdf_path).with_context(|| {
+        format!(
+            "[Steam] Failed to read library folders file : {}",
+            vdf_path.to_string_lossy()
+        )
+    })?;
+    Ok(library_folders_from_vdf(&parse_vdf(&content)?))
+}
+
+/// Resolves the real directory of the native Steam installation.
+/// Symlinks are followed, so a dangling `~/.steam/steam` is skipped in favor of the next candidate.
+/// When the candidate has a libraryfolders.vdf, the library holding the Steam client config is preferred
+pub fn find_steam_root() -> Option<PathBuf> {
+    steam_root_candidates()
+        .into_iter()
+        .filter_map(|candidate| fs::canonicalize(candidate).ok())
+        .find(|candidate| candidate.is_dir())
+        .map(|root| {
+            library_folders(&root)
+                .unwrap_or_default()
+                .into_iter()
+                .find(|library| library.join(LIBRARY_FOLDERS_FILE).is_file())
+                .unwrap_or(root)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library_folders_from_vdf() {
+        let conditions = &[
+            (
+                r#""libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/deck/.local/share/Steam"
+		"label"		""
+		"contentid"		"1234"
+		"apps"
+		{
+			"228980"		"123"
+		}
+	}
+	"1"
+	{
+		"path"		"/run/media/mmcblk0p1"
+	}
+}"#,
+                vec!["/home/deck/.local/share/Steam", "/run/media/mmcblk0p1"],
+                "current format",
+            ),
+            (
+                r#""LibraryFolders"
+{
+	// legacy files have metadata next to the libraries
+	"TimeNextStatsReport"		"1690000000"
+	"ContentStatsID"		"-123"
+	"1"		"/mnt/games/SteamLibrary"
+}"#,
+                vec!["/mnt/games/SteamLibrary"],
+                "legacy format",
+            ),
+        ];
+
+        for (input, expected, desc) in conditions {
+            let vdf = parse_vdf(input);
+            assert!(
+                vdf.is_ok(),
+                "case : '{}' test: parse_vdf returned error",
+                desc
+            );
+
+            let folders = library_folders_from_vdf(&vdf.unwrap());
+            let expected: Vec<PathBuf> = expected.iter().map(PathBuf::from).collect();
+            assert_eq!(
+                folders, expected,
+                "case : '{}' test: library_folders_from_vdf wrong",
+                desc
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_vdf_errors() {
+        assert!(parse_vdf(r#""libraryfolders" { "0" { "path" "/" }"#).is_err());
+        assert!(parse_vdf(r#""libraryfolders" { "0" "#).is_err());
+        assert!(parse_vdf(r#""unterminated"#).is_err());
+    }
+}
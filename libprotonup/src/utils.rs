@@ -21,3 +21,193 @@ pub fn expand_tilde<P: AsRef<Path>>(path_user_input: P) -> Option<PathBuf> {
         }
     })
 }
+
+/// Checks if a file name matches a simple glob pattern, where `*` matches any sequence of characters
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // split always yields at least one item
+    let first = parts.next().unwrap();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // no wildcard in the pattern, the whole name must have been consumed
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Checks if an installed folder name holds the given tag.
+/// Some variants use the tag as the folder name, others wrap it, like "lutris-GE-Proton8-26-x86_64".
+/// The tag must not be part of a longer version, "8.0" isn't found in "wine-8.0.1-staging-tkg-amd64".
+/// A tag can still be found in the folder of a longer tag, like "9.0" in "wine-9.0-rc5-staging-tkg-amd64", see `folder_release`
+pub fn folder_matches_tag(folder: &str, tag: &str) -> bool {
+    let boundary = |c: Option<char>| !c.is_some_and(|c| c.is_alphanumeric() || c == '.');
+    folder.match_indices(tag).any(|(index, _)| {
+        boundary(folder[..index].chars().next_back())
+            && boundary(folder[index + tag.len()..].chars().next())
+    })
+}
+
+/// Returns the index of the release an installed folder holds, among the release tags.
+/// A tag equal to the folder name wins, then the longest tag found in it,
+/// so "wine-9.0-rc5-staging-tkg-amd64" holds "9.0-rc5" rather than "9.0"
+pub fn folder_release(folder: &str, release_tags: &[String]) -> Option<usize> {
+    release_tags
+        .iter()
+        .position(|tag| tag == folder)
+        .or_else(|| {
+            release_tags
+                .iter()
+                .enumerate()
+                .filter(|(_, tag)| folder_matches_tag(folder, tag))
+                .max_by_key(|(_, tag)| tag.len())
+                .map(|(index, _)| index)
+        })
+}
+
+/// Returns how many releases the newest installed one is behind the latest, given the installed folders or tags
+/// and the release tags ordered newest first, see `folder_release`. None when no installed folder holds one of the releases
+pub fn releases_behind(folders: &[String], release_tags: &[String]) -> Option<usize> {
+    folders
+        .iter()
+        .filter_map(|folder| folder_release(folder, release_tags))
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_pattern() {
+        let conditions = &[
+            ("*.tar.gz", "GE-Proton8-25.tar.gz", true),
+            ("*.tar.gz", "GE-Proton8-25.sha512sum", false),
+            ("luxtorpeda*.tar.xz", "luxtorpeda-v68.0.0.tar.xz", true),
+            ("boxtron*.tar.xz", "boxtron.tar.xz", true),
+            (
+                "wine-*-staging-tkg-amd64.tar.xz",
+                "wine-9.0-staging-tkg-amd64.tar.xz",
+                true,
+            ),
+            (
+                "wine-*-staging-tkg-amd64.tar.xz",
+                "wine-9.0-staging-tkg-amd64-wow64.tar.xz",
+                false,
+            ),
+            (
+                "wine-*-staging-tkg-amd64.tar.xz",
+                "wine-9.0-amd64.tar.xz",
+                false,
+            ),
+            (
+                "proton_tkg_*.tar.zst",
+                "proton_tkg_8.16.r4.g77d5bee7.tar.zst",
+                true,
+            ),
+            ("exact.tar.gz", "exact.tar.gz", true),
+            ("exact.tar.gz", "exact.tar.gz.sig", false),
+            ("a*a", "a", false),
+        ];
+
+        for (pattern, name, expected) in conditions {
+            assert_eq!(
+                matches_pattern(pattern, name),
+                *expected,
+                "case : '{}' '{}' test: matches_pattern wrong",
+                pattern,
+                name
+            );
+        }
+    }
+    #[test]
+    fn test_folder_matches_tag() {
+        let conditions = &[
+            ("GE-Proton8-25", "GE-Proton8-25", true),
+            ("lutris-GE-Proton8-26-x86_64", "GE-Proton8-26", true),
+            ("lutris-GE-Proton8-26-x86_64", "GE-Proton8-2", false),
+            ("GE-Proton8-25", "GE-Proton8-2", false),
+            ("luxtorpeda", "v68.0.0", false),
+            ("wine-8.0.1-staging-tkg-amd64", "8.0", false),
+            ("wine-8.0.1-staging-tkg-amd64", "8.0.1", true),
+            ("wine-9.0-rc5-staging-tkg-amd64", "9.0-rc5", true),
+            ("wine-9.0-staging-tkg-amd64", "9.0", true),
+        ];
+
+        for (folder, tag, expected) in conditions {
+            assert_eq!(
+                folder_matches_tag(folder, tag),
+                *expected,
+                "case : '{}' '{}' test: folder_matches_tag wrong",
+                folder,
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn test_folder_release() {
+        let tags: Vec<String> = ["9.0", "9.0-rc5", "8.0.1", "8.0"]
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect();
+        let conditions = &[
+            ("wine-9.0-staging-tkg-amd64", Some(0)),
+            ("wine-9.0-rc5-staging-tkg-amd64", Some(1)),
+            ("wine-8.0.1-staging-tkg-amd64", Some(2)),
+            ("wine-8.0-staging-tkg-amd64", Some(3)),
+            ("8.0", Some(3)),
+            ("wine-7.0-staging-tkg-amd64", None),
+        ];
+
+        for (folder, expected) in conditions {
+            assert_eq!(
+                folder_release(folder, &tags),
+                *expected,
+                "case : '{}' test: folder_release wrong",
+                folder
+            );
+        }
+    }
+
+    #[test]
+    fn test_releases_behind() {
+        let ge_tags: &[&str] = &["GE-Proton8-27", "GE-Proton8-26", "GE-Proton8-25"];
+        let wine_tags: &[&str] = &["9.0", "9.0-rc5", "8.0.1", "8.0"];
+        let conditions: &[(&[&str], &[&str], Option<usize>)] = &[
+            (ge_tags, &["GE-Proton8-27"], Some(0)),
+            (ge_tags, &["GE-Proton8-25", "GE-Proton8-26"], Some(1)),
+            (ge_tags, &["lutris-GE-Proton8-25-x86_64"], Some(2)),
+            (ge_tags, &["GE-Proton7-55"], None),
+            (ge_tags, &[], None),
+            (wine_tags, &["9.0"], Some(0)),
+            (wine_tags, &["9.0-rc5"], Some(1)),
+            (wine_tags, &["8.0.1"], Some(2)),
+            (wine_tags, &["8.0"], Some(3)),
+            (wine_tags, &["8.0", "8.0.1"], Some(2)),
+            (wine_tags, &["wine-9.0-rc5-staging-tkg-amd64"], Some(1)),
+            (wine_tags, &["wine-8.0.1-staging-tkg-amd64"], Some(2)),
+            (wine_tags, &["7.0"], None),
+        ];
+
+        for (tags, folders, expected) in conditions {
+            let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+            let folders: Vec<String> = folders.iter().map(|folder| folder.to_string()).collect();
+            assert_eq!(
+                releases_behind(&folders, &tags),
+                *expected,
+                "case : '{:?}' test: releases_behind wrong",
+                folders
+            );
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use super::constants::*;
+use crate::{apps::App, files::ArchiveRelease, utils};
+use serde::{Deserialize, Serialize, Serializer};
 use std::{fmt, str::FromStr};
 
 /// Struct used to build GitHub api request URLs.
@@ -36,11 +38,139 @@ impl VariantGithubParameters {
     }
 }
 
+/// Rules used to pick a Variant's files among the assets of a Release.
+/// Patterns are file names where `*` matches any sequence of characters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetRules {
+    /// Patterns of the archive to install, in order of preference
+    pub archive_patterns: Vec<String>,
+    /// Pattern of the sha512sum file, None for variants that don't publish one
+    pub checksum_pattern: Option<String>,
+    /// Name of the archive's top-level folder, the folder the release is installed into
+    pub install_folder: InstallFolder,
+}
+
+/// Name of the folder a Variant's releases are installed into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstallFolder {
+    /// The release tag between a prefix and a suffix, like "lutris-GE-Proton8-26-x86_64"
+    Tagged { prefix: String, suffix: String },
+    /// The same name for every release, like "luxtorpeda". The installed release is the tag recorded in it, see `files::INSTALLED_TAG_FILE`
+    Fixed(String),
+    /// Not known, the release is found in the folder name with `utils::folder_release`
+    Unknown,
+}
+
+impl InstallFolder {
+    fn tagged(prefix: &str, suffix: &str) -> InstallFolder {
+        InstallFolder::Tagged {
+            prefix: prefix.to_owned(),
+            suffix: suffix.to_owned(),
+        }
+    }
+}
+
+impl AssetRules {
+    fn new(
+        archive_patterns: &[&str],
+        checksum_pattern: Option<&str>,
+        install_folder: InstallFolder,
+    ) -> AssetRules {
+        AssetRules {
+            archive_patterns: archive_patterns.iter().map(|p| p.to_string()).collect(),
+            checksum_pattern: checksum_pattern.map(str::to_owned),
+            install_folder,
+        }
+    }
+
+    /// Returns what the archive of the release holds, checked and recorded when unpacking, see `files::unpack_verified`
+    pub fn archive_release(&self, tag: &str) -> ArchiveRelease {
+        ArchiveRelease {
+            tag: tag.to_owned(),
+            folder: match &self.install_folder {
+                InstallFolder::Tagged { prefix, suffix } => {
+                    Some(format!("{}{}{}", prefix, tag, suffix))
+                }
+                InstallFolder::Fixed(name) => Some(name.clone()),
+                InstallFolder::Unknown => None,
+            },
+        }
+    }
+
+    /// Returns the index of the release an installed folder holds among the release tags, None if it isn't one of this Variant's.
+    /// `recorded_tag` is the tag recorded in the folder when it was installed, if any
+    pub fn folder_release(
+        &self,
+        folder: &str,
+        recorded_tag: Option<&str>,
+        release_tags: &[String],
+    ) -> Option<usize> {
+        let position = |tag: &str| release_tags.iter().position(|release| release == tag);
+        match &self.install_folder {
+            InstallFolder::Tagged { prefix, suffix } => position(
+                folder
+                    .strip_prefix(prefix.as_str())?
+                    .strip_suffix(suffix.as_str())?,
+            ),
+            InstallFolder::Fixed(name) if folder == name => position(recorded_tag?),
+            InstallFolder::Fixed(_) => None,
+            InstallFolder::Unknown => match recorded_tag {
+                Some(tag) => position(tag),
+                None => utils::folder_release(folder, release_tags),
+            },
+        }
+    }
+
+    /// Returns the index of the first archive pattern matched by the asset name, lower is preferred
+    pub fn archive_preference(&self, asset_name: &str) -> Option<usize> {
+        self.archive_patterns
+            .iter()
+            .position(|pattern| utils::matches_pattern(pattern, asset_name))
+    }
+
+    /// Returns true if the asset name is the checksum file
+    pub fn is_checksum(&self, asset_name: &str) -> bool {
+        self.checksum_pattern
+            .as_ref()
+            .is_some_and(|pattern| utils::matches_pattern(pattern, asset_name))
+    }
+}
+
+/// A variant declared by the user in the config file, for forks and builds not supported out of the box
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomVariant {
+    /// Name shown in menus, also used to pick the variant on the command line
+    pub name: String,
+    /// URL of the repository server (GitHub compatible URL only at the moment)
+    #[serde(default = "default_repository_url")]
+    pub repository_url: String,
+    /// GitHub account for the variant
+    pub repository_account: String,
+    /// name of the repository
+    pub repository_name: String,
+    /// Pattern of the archive to install, `*` matches any sequence of characters
+    pub asset_pattern: String,
+    /// Pattern of the sha512sum file, if the releases have one
+    #[serde(default)]
+    pub checksum_pattern: Option<String>,
+    /// Apps the variant can be installed for
+    pub target_apps: Vec<App>,
+}
+
+fn default_repository_url() -> String {
+    GITHUB_URL.to_owned()
+}
+
 /// Variant is an enum with all supported "Proton" versions
 #[derive(Debug, Clone)]
 pub enum Variant {
     GEProton,
     WineGE,
+    Luxtorpeda,
+    Boxtron,
+    ProtonTkg,
+    Kron4ekWine,
+    Custom(Box<CustomVariant>),
 }
 
 impl fmt::Display for Variant {
@@ -49,28 +179,101 @@ impl fmt::Display for Variant {
         match self {
             Variant::GEProton => write!(f, "GEProton"),
             Variant::WineGE => write!(f, "WineGE"),
+            Variant::Luxtorpeda => write!(f, "Luxtorpeda"),
+            Variant::Boxtron => write!(f, "Boxtron"),
+            Variant::ProtonTkg => write!(f, "ProtonTkg"),
+            Variant::Kron4ekWine => write!(f, "Kron4ekWine"),
+            Variant::Custom(custom) => write!(f, "{}", custom.name),
         }
     }
 }
 
+impl Serialize for Variant {
+    /// Variants are serialized by name, the same representation FromStr and `Config::find_variant` accept
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl FromStr for Variant {
     type Err = ();
-    /// Converts a "GEProton" or "WineGE" string into its respective Variant
+    /// Converts a Variant name, like "GEProton" or "WineGE", into its respective Variant.
+    /// Custom variants only exist in the config, look them up with `Config::find_variant`
     fn from_str(input: &str) -> Result<Variant, Self::Err> {
         match input {
             "GEProton" => Ok(Variant::GEProton),
             "WineGE" => Ok(Variant::WineGE),
+            "Luxtorpeda" => Ok(Variant::Luxtorpeda),
+            "Boxtron" => Ok(Variant::Boxtron),
+            "ProtonTkg" => Ok(Variant::ProtonTkg),
+            "Kron4ekWine" => Ok(Variant::Kron4ekWine),
             _ => Err(()),
         }
     }
 }
 
 impl Variant {
-    /// returns the application target for the Variant. Steam and Lutris are the current options
-    pub fn intended_application(&self) -> &str {
+    /// returns the application target for the Variant. Steam and Lutris are the built-in options, custom variants list their own
+    pub fn intended_application(&self) -> String {
         match self {
-            Variant::GEProton => "Steam",
-            Variant::WineGE => "Lutris",
+            Variant::GEProton | Variant::Luxtorpeda | Variant::Boxtron | Variant::ProtonTkg => {
+                "Steam".to_owned()
+            }
+            Variant::WineGE | Variant::Kron4ekWine => "Lutris".to_owned(),
+            Variant::Custom(custom) => custom
+                .target_apps
+                .iter()
+                .map(|app| app.to_string())
+                .collect::<Vec<String>>()
+                .join("/"),
+        }
+    }
+
+    /// Returns the rules used to find this Variant's archive and sha512sum file in a Release.
+    /// GE builds ship a single archive and a sha512sum, the others are picked by name and have no sha512sum
+    pub fn asset_rules(&self) -> AssetRules {
+        match self {
+            Variant::GEProton => AssetRules::new(
+                &["*.tar.gz", "*.tar.xz"],
+                Some("*sha512sum"),
+                InstallFolder::tagged("", ""),
+            ),
+            Variant::WineGE => AssetRules::new(
+                &["*.tar.gz", "*.tar.xz"],
+                Some("*sha512sum"),
+                InstallFolder::tagged("lutris-", "-x86_64"),
+            ),
+            Variant::Luxtorpeda => AssetRules::new(
+                &["luxtorpeda*.tar.xz", "luxtorpeda*.tar.gz"],
+                None,
+                InstallFolder::Fixed("luxtorpeda".to_owned()),
+            ),
+            Variant::Boxtron => AssetRules::new(
+                &["boxtron*.tar.xz", "boxtron*.tar.gz"],
+                None,
+                InstallFolder::Fixed("boxtron".to_owned()),
+            ),
+            // Releases also contain wine-tkg Arch packages, only the proton_tkg archives are installable
+            Variant::ProtonTkg => AssetRules::new(
+                &[
+                    "proton_tkg_*.tar.xz",
+                    "proton_tkg_*.tar.gz",
+                    "proton_tkg_*.tar.zst",
+                ],
+                None,
+                InstallFolder::Unknown,
+            ),
+            // Releases have vanilla, staging and wow64 builds for x86 and amd64, staging-tkg amd64 is the one used by Lutris
+            Variant::Kron4ekWine => AssetRules::new(
+                &["wine-*-staging-tkg-amd64.tar.xz"],
+                None,
+                InstallFolder::tagged("wine-", "-staging-tkg-amd64"),
+            ),
+            Variant::Custom(custom) => AssetRules {
+                archive_patterns: vec![custom.asset_pattern.clone()],
+                checksum_pattern: custom.checksum_pattern.clone(),
+                install_folder: InstallFolder::Unknown,
+            },
         }
     }
 
@@ -89,9 +292,104 @@ impl Variant {
                 repository_name: WINEGE_GITHUB_REPO.to_owned(),
                 repository_account: GE_GITHUB_ACCOUNT.to_owned(),
             },
+            Variant::Luxtorpeda => VariantGithubParameters {
+                variant_ref: Variant::Luxtorpeda,
+                repository_url: GITHUB_URL.to_owned(),
+                repository_name: LUXTORPEDA_GITHUB_REPO.to_owned(),
+                repository_account: LUXTORPEDA_GITHUB_ACCOUNT.to_owned(),
+            },
+            Variant::Boxtron => VariantGithubParameters {
+                variant_ref: Variant::Boxtron,
+                repository_url: GITHUB_URL.to_owned(),
+                repository_name: BOXTRON_GITHUB_REPO.to_owned(),
+                repository_account: BOXTRON_GITHUB_ACCOUNT.to_owned(),
+            },
+            Variant::ProtonTkg => VariantGithubParameters {
+                variant_ref: Variant::ProtonTkg,
+                repository_url: GITHUB_URL.to_owned(),
+                repository_name: PROTONTKG_GITHUB_REPO.to_owned(),
+                repository_account: PROTONTKG_GITHUB_ACCOUNT.to_owned(),
+            },
+            Variant::Kron4ekWine => VariantGithubParameters {
+                variant_ref: Variant::Kron4ekWine,
+                repository_url: GITHUB_URL.to_owned(),
+                repository_name: KRON4EK_WINE_GITHUB_REPO.to_owned(),
+                repository_account: KRON4EK_WINE_GITHUB_ACCOUNT.to_owned(),
+            },
+            Variant::Custom(custom) => VariantGithubParameters::new_custom(
+                self.clone(),
+                custom.repository_url.clone(),
+                custom.repository_account.clone(),
+                custom.repository_name.clone(),
+            ),
         }
     }
 }
 
 // ALL_VARIANTS is a shorthand to all app variants
-pub static ALL_VARIANTS: &[Variant] = &[Variant::GEProton, Variant::WineGE];
+pub static ALL_VARIANTS: &[Variant] = &[
+    Variant::GEProton,
+    Variant::WineGE,
+    Variant::Luxtorpeda,
+    Variant::Boxtron,
+    Variant::ProtonTkg,
+    Variant::Kron4ekWine,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files;
+
+    #[test]
+    fn test_archive_patterns_supported() {
+        for variant in ALL_VARIANTS {
+            for pattern in variant.asset_rules().archive_patterns {
+                assert!(
+                    files::archive_extension(&pattern).is_some(),
+                    "case : '{} {}' test: archive pattern has no supported extension",
+                    variant,
+                    pattern
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_folder_release() {
+        let tags: Vec<String> = ["GE-Proton8-26", "GE-Proton8-2", "0.51.0", "0.5.0"]
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect();
+
+        let conditions = &[
+            (Variant::GEProton, "GE-Proton8-26", None, Some(0)),
+            (Variant::GEProton, "GE-Proton8-2", None, Some(1)),
+            (Variant::GEProton, "GE-Proton8-2-custom", None, None),
+            (
+                Variant::WineGE,
+                "lutris-GE-Proton8-26-x86_64",
+                None,
+                Some(0),
+            ),
+            (Variant::WineGE, "GE-Proton8-26", None, None),
+            (Variant::Luxtorpeda, "luxtorpeda", Some("0.51.0"), Some(2)),
+            (Variant::Luxtorpeda, "luxtorpeda", Some("0.5.0"), Some(3)),
+            (Variant::Luxtorpeda, "luxtorpeda", None, None),
+            (Variant::Luxtorpeda, "boxtron", Some("0.5.0"), None),
+            (Variant::ProtonTkg, "proton_tkg_0.5.0", None, Some(3)),
+            (Variant::ProtonTkg, "proton_tkg", Some("0.51.0"), Some(2)),
+        ];
+
+        for (variant, folder, recorded_tag, expected) in conditions {
+            let result = variant
+                .asset_rules()
+                .folder_release(folder, *recorded_tag, &tags);
+            assert_eq!(
+                result, *expected,
+                "case : '{} {}' test: folder_release wrong",
+                variant, folder
+            );
+        }
+    }
+}
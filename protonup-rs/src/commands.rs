@@ -0,0 +1,503 @@
+use clap::{Subcommand, ValueEnum};
+use serde::de::{value, IntoDeserializer};
+use serde::Deserialize;
+
+use std::collections::{hash_map::Entry, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use libprotonup::{
+    apps::{self, App, AppInstallations},
+    config::Config,
+    files,
+    github::{self, Release},
+    utils,
+    variants::Variant,
+};
+
+use crate::{
+    download,
+    output::{InstallResult, InstalledVersions, OutputFormat, RemoveResult, UpdateCheck},
+};
+
+/// Exit code of the check command when updates are available
+pub(crate) const UPDATES_AVAILABLE_EXIT_CODE: i32 = 100;
+
+/// Non interactive commands, for scripts and provisioning
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Install a variant without prompting. Installs into every detected app supporting it, unless --app or --dir is set
+    Install {
+        /// Variant to install, like GEProton, WineGE, or the name of a custom variant
+        variant: String,
+        /// Release tag to install, the latest release when unset
+        #[arg(long)]
+        version: Option<String>,
+        /// Only install into the installations of this app
+        #[arg(long, conflicts_with = "dir")]
+        app: Option<AppArg>,
+        /// Install into this directory instead of an app's
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Install a local tar archive, plain or compressed with gzip, xz, zstd or bzip2, without calling GitHub,
+    /// into a single installation picked with --installation or --app, or into --dir
+    InstallFile {
+        /// Path of the archive
+        archive: PathBuf,
+        /// sha512sum file to check the archive with
+        #[arg(long)]
+        sha512sum: Option<PathBuf>,
+        /// Install into the installation of this app, fails if more than one is detected
+        #[arg(long, conflicts_with_all = ["dir", "installation"], required_unless_present_any = ["dir", "installation"])]
+        app: Option<AppArg>,
+        /// Install into this installation, named like in the install_dirs table of the config: steam, steam_flatpak, heroic_proton, heroic_wine...
+        #[arg(long, conflicts_with = "dir", value_parser = parse_installation)]
+        installation: Option<AppInstallations>,
+        /// Install into this directory
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// List the versions installed in every detected app
+    ListInstalled,
+    /// List the releases available for a variant
+    ListAvailable {
+        /// Variant to list, like GEProton, WineGE, or the name of a custom variant
+        variant: String,
+        /// Only list this many releases, the newest ones. Lists all of them when unset
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Check the installed versions of every detected app against the latest releases, without installing anything.
+    /// Exits with code 100 when updates are available
+    Check,
+    /// Remove an installed version from every detected app, unless --app or --dir is set
+    Remove {
+        /// Name of the installed version's folder, as printed by list-installed, like GE-Proton8-25 or lutris-GE-Proton8-26-x86_64
+        tag: String,
+        /// Only remove from the installations of this app
+        #[arg(long, conflicts_with = "dir")]
+        app: Option<AppArg>,
+        /// Remove from this directory instead of an app's
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+/// App names accepted on the command line
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub(crate) enum AppArg {
+    Steam,
+    Lutris,
+    Heroic,
+    Bottles,
+}
+
+impl From<AppArg> for App {
+    fn from(app: AppArg) -> App {
+        match app {
+            AppArg::Steam => App::Steam,
+            AppArg::Lutris => App::Lutris,
+            AppArg::Heroic => App::HeroicGamesLauncher,
+            AppArg::Bottles => App::Bottles,
+        }
+    }
+}
+
+/// Runs a command, returning an error message if it failed
+pub(crate) async fn run_command(
+    command: Command,
+    config: &Config,
+    output: OutputFormat,
+) -> Result<(), String> {
+    match command {
+        Command::Install {
+            variant,
+            version,
+            app,
+            dir,
+        } => install(&variant, version.as_deref(), app, dir, config, output).await,
+        Command::InstallFile {
+            archive,
+            sha512sum,
+            app,
+            installation,
+            dir,
+        } => install_file(
+            &archive,
+            sha512sum.as_deref(),
+            app,
+            installation,
+            dir,
+            config,
+            output,
+        ),
+        Command::ListInstalled => list_installed(config, output),
+        Command::ListAvailable { variant, limit } => {
+            list_available(&variant, limit, config, output).await
+        }
+        Command::Check => check(config, output).await,
+        Command::Remove { tag, app, dir } => remove(&tag, app, dir, config, output),
+    }
+}
+
+fn find_variant(name: &str, config: &Config) -> Result<Variant, String> {
+    config.find_variant(name).ok_or_else(|| {
+        format!(
+            "Unknown variant {}. Available variants: {}",
+            name,
+            config
+                .all_variants()
+                .iter()
+                .map(|variant| variant.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    })
+}
+
+/// Parses an installation name, the snake_case names of the install_dirs table of the config
+fn parse_installation(name: &str) -> Result<AppInstallations, String> {
+    AppInstallations::deserialize(IntoDeserializer::<value::Error>::into_deserializer(name))
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the detected installations of the app, or of every app when unset
+fn detected_installations(app: Option<AppArg>) -> Vec<AppInstallations> {
+    match app {
+        Some(app) => App::from(app).detect_installation_method(),
+        None => apps::list_installed_apps(),
+    }
+}
+
+/// Returns the directories to work on, the one passed with --dir or the install directories of the detected apps.
+/// `variant` filters out the installations it can't be installed into
+fn target_dirs(
+    app: Option<AppArg>,
+    dir: Option<PathBuf>,
+    variant: Option<&Variant>,
+    config: &Config,
+) -> Result<Vec<PathBuf>, String> {
+    if let Some(dir) = dir {
+        return utils::expand_tilde(&dir)
+            .map(|dir| vec![dir])
+            .ok_or_else(|| format!("Invalid directory {}", dir.display()));
+    }
+    let dirs: Vec<PathBuf> = detected_installations(app)
+        .iter()
+        .filter(|app_inst| {
+            variant.is_none_or(|variant| {
+                config
+                    .compatible_variants(app_inst)
+                    .iter()
+                    .any(|compatible| compatible.to_string() == variant.to_string())
+            })
+        })
+        .map(|app_inst| config.install_dir(app_inst))
+        .collect();
+    if dirs.is_empty() {
+        return Err("No matching app installation found".to_string());
+    }
+    Ok(dirs)
+}
+
+async fn install(
+    variant: &str,
+    version: Option<&str>,
+    app: Option<AppArg>,
+    dir: Option<PathBuf>,
+    config: &Config,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let variant = find_variant(variant, config)?;
+    let install_dirs = target_dirs(app, dir, Some(&variant), config)?;
+
+    let source = variant.get_github_parameters();
+    let release = match version {
+        Some(version) => {
+            github::get_release_by_tag(&source, version, config.github_token().as_deref())
+                .await
+                .map_err(|e| format!("Version {} not found for {}: {}", version, variant, e))?
+        }
+        None => github::get_latest_release(&source, config.github_token().as_deref())
+            .await
+            .map_err(|e| format!("Failed to fetch Github data: {}", e))?,
+    };
+
+    let download = release.get_download_info(&variant);
+    // A single directory is installed while downloading, the archive is only stored to be unpacked into several ones
+    let file = match install_dirs.len() {
+        1 => None,
+        _ => Some(download::download_file(download.clone(), &config.temp_dir(), output).await?),
+    };
+    let mut results = vec![];
+    for install_dir in install_dirs {
+        let unpacked = match &file {
+            Some(file) => {
+                download::unpack_file(file, &install_dir, &variant, &release.tag_name, output).await
+            }
+            None => {
+                download::download_and_unpack(download.clone(), &install_dir, &variant, output)
+                    .await
+            }
+        };
+        results.push(InstallResult {
+            variant: Some(variant.clone()),
+            version: release.tag_name.clone(),
+            install_dir,
+            up_to_date: false,
+            success: unpacked.is_ok(),
+            error: unpacked.err(),
+        });
+    }
+    if let Some(file) = file {
+        if let Err(e) = fs::remove_file(&file) {
+            output.status(&format!("Failed removing {}: {}", file.display(), e));
+        }
+    }
+    output.records(&results);
+    match results.iter().find_map(|result| result.error.clone()) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn install_file(
+    archive: &Path,
+    sha512sum: Option<&Path>,
+    app: Option<AppArg>,
+    installation: Option<AppInstallations>,
+    dir: Option<PathBuf>,
+    config: &Config,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let archive = utils::expand_tilde(archive)
+        .filter(|archive| archive.is_file())
+        .ok_or_else(|| format!("Archive {} not found", archive.display()))?;
+    let file_name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // The archive format is detected from its content, the extension is only stripped from the version
+    let version = match files::archive_extension(&file_name) {
+        Some(extension) => file_name[..file_name.len() - extension.len() - 1].to_string(),
+        None => file_name.clone(),
+    };
+    // Local archives can be of any variant, they are only installed where the user picked
+    // and their top-level folder isn't checked against a release
+    let install_dirs = match installation {
+        Some(installation) => vec![config.install_dir(&installation)],
+        None if dir.is_some() => target_dirs(None, dir, None, config)?,
+        None => match detected_installations(app).as_slice() {
+            [installation] => vec![config.install_dir(installation)],
+            [] => return Err("No matching app installation found".to_string()),
+            installations => {
+                return Err(format!(
+                    "Several installations found: {}. Pick one with --installation",
+                    installations
+                        .iter()
+                        .map(|installation| installation.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ))
+            }
+        },
+    };
+
+    match sha512sum.and_then(utils::expand_tilde) {
+        Some(sha512sum) => {
+            let git_hash = fs::read_to_string(&sha512sum)
+                .map_err(|e| format!("Failed reading {}: {}", sha512sum.display(), e))?;
+            output.status("Checking file integrity");
+            if !files::hash_check_file(archive.to_string_lossy().to_string(), git_hash)
+                .map_err(|e| e.to_string())?
+            {
+                return Err("Failed checking file hash".to_string());
+            }
+        }
+        None => output.status(&format!(
+            "No sha512sum given for {}, skipping integrity check",
+            file_name
+        )),
+    }
+
+    let mut results = vec![];
+    for install_dir in install_dirs {
+        output.status("Unpacking files into install location. Please wait");
+        let unpacked = fs::create_dir_all(&install_dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                files::decompress(&archive, &install_dir, None).map_err(|e| e.to_string())
+            })
+            .map_err(|e| format!("Failed unpacking into {}: {}", install_dir.display(), e));
+        if unpacked.is_ok() {
+            output.status(&format!(
+                "Done! {} installed in {}",
+                version,
+                install_dir.display()
+            ));
+        }
+        results.push(InstallResult {
+            variant: None,
+            version: version.clone(),
+            install_dir,
+            up_to_date: false,
+            success: unpacked.is_ok(),
+            error: unpacked.err(),
+        });
+    }
+    output.records(&results);
+    match results.iter().find_map(|result| result.error.clone()) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn list_installed(config: &Config, output: OutputFormat) -> Result<(), String> {
+    let mut records = vec![];
+    for app_inst in apps::list_installed_apps() {
+        let install_dir = config.install_dir(&app_inst);
+        output.status(&format!("{} ({}):", app_inst, install_dir.display()));
+        let versions = files::list_folders_in_path(&install_dir).unwrap_or_default();
+        if versions.is_empty() {
+            output.status("  No versions installed");
+        }
+        versions
+            .iter()
+            .for_each(|version| output.status(&format!("  {}", version)));
+        records.push(InstalledVersions {
+            app: app_inst.into_app(),
+            installation: app_inst,
+            install_dir,
+            versions,
+        });
+    }
+    output.records(&records);
+    Ok(())
+}
+
+async fn list_available(
+    variant: &str,
+    limit: Option<usize>,
+    config: &Config,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let variant = find_variant(variant, config)?;
+    let release_list = github::list_releases(
+        &variant.get_github_parameters(),
+        limit,
+        config.github_token().as_deref(),
+    )
+    .await
+    .map_err(|e| format!("Failed to fetch Github data: {}", e))?;
+    for release in &release_list {
+        output.status(&release.to_string());
+    }
+    output.records(&release_list);
+    Ok(())
+}
+
+/// Compares the installed versions of the variants Quick Update installs with their releases
+async fn check(config: &Config, output: OutputFormat) -> Result<(), String> {
+    // Installations often share variants, fetch each release list once
+    let mut releases: HashMap<String, Vec<Release>> = HashMap::new();
+    let mut checks = vec![];
+    for app_inst in apps::list_installed_apps()
+        .into_iter()
+        .filter(|app_inst| config.is_default_app(app_inst.into_app()))
+    {
+        let install_dir = config.install_dir(&app_inst);
+        let preferred = config.preferred_variant(&app_inst);
+        let custom_variants = config
+            .custom_variants_for(&app_inst)
+            .into_iter()
+            .filter(|custom| custom.to_string() != preferred.to_string());
+        for variant in std::iter::once(preferred.clone()).chain(custom_variants) {
+            let release_list = match releases.entry(variant.to_string()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(
+                    github::list_releases(
+                        &variant.get_github_parameters(),
+                        None,
+                        config.github_token().as_deref(),
+                    )
+                    .await
+                    .map_err(|e| format!("Failed to fetch Github data: {}", e))?,
+                ),
+            };
+            let tags: Vec<String> = release_list
+                .iter()
+                .map(|release| release.tag_name.clone())
+                .collect();
+            // Folders are matched by the variant's folder name, see files::installed_releases
+            let installed_tags: Vec<String> =
+                files::installed_releases(&install_dir, &variant.asset_rules(), &tags)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(_, tag)| tag)
+                    .collect();
+            let releases_behind = utils::releases_behind(&installed_tags, &tags);
+            let latest = tags.first().cloned().unwrap_or_default();
+            output.status(&match releases_behind {
+                None => format!("{}: no installed version of {} found", app_inst, variant),
+                Some(0) => format!("{}: {} {} is up to date", app_inst, variant, latest),
+                Some(behind) => format!(
+                    "{}: {} {} is {} release(s) behind {}",
+                    app_inst, variant, tags[behind], behind, latest
+                ),
+            });
+            checks.push(UpdateCheck {
+                installation: app_inst.clone(),
+                variant,
+                install_dir: install_dir.clone(),
+                installed: releases_behind.map(|behind| tags[behind].clone()),
+                latest,
+                releases_behind,
+                outdated: releases_behind.is_some_and(|behind| behind > 0),
+            });
+        }
+    }
+    let outdated = checks.iter().any(|check| check.outdated);
+    output.records(&checks);
+    if outdated {
+        std::process::exit(UPDATES_AVAILABLE_EXIT_CODE)
+    }
+    Ok(())
+}
+
+fn remove(
+    tag: &str,
+    app: Option<AppArg>,
+    dir: Option<PathBuf>,
+    config: &Config,
+    output: OutputFormat,
+) -> Result<(), String> {
+    // Only a folder named exactly like the tag is removed, never a path leading elsewhere
+    let mut components = Path::new(tag).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return Err(format!("{} is not the name of a version folder", tag));
+    }
+
+    let mut removed = vec![];
+    for install_dir in target_dirs(app, dir, None, config)? {
+        if !files::check_if_exists(&install_dir, tag) {
+            continue;
+        }
+        let version_dir = install_dir.join(tag);
+        files::remove_dir_all(&version_dir)
+            .map_err(|e| format!("Error deleting {}: {}", version_dir.display(), e))?;
+        output.status(&format!("{} deleted successfully", version_dir.display()));
+        removed.push(RemoveResult {
+            version: tag.to_owned(),
+            install_dir,
+        });
+    }
+    if removed.is_empty() {
+        return Err(format!("Version {} is not installed", tag));
+    }
+    output.records(&removed);
+    Ok(())
+}
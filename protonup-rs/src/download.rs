@@ -1,3 +1,5 @@
+use clap::ValueEnum;
+
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 
 use inquire::{Select, Text};
@@ -5,56 +7,185 @@ use inquire::{Select, Text};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::{
-    sync::{atomic::Ordering, Arc},
-    thread,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
-use crate::{file_path, helper_menus};
+use crate::{
+    commands::AppArg,
+    file_path, helper_menus,
+    output::{InstallResult, OutputFormat, QuickUpdateReport},
+};
 
 use libprotonup::{
-    apps, constants, files,
+    apps,
+    config::Config,
+    constants, files,
     github::{self, Download, Release},
     utils,
-    variants::{self, Variant},
+    variants::Variant,
 };
 
-pub(crate) async fn download_file(download: Download) -> Result<PathBuf, String> {
-    let mut temp_dir = utils::expand_tilde(constants::TEMP_DIR).unwrap();
+pub(crate) async fn download_file(
+    download: Download,
+    temp_dir: &Path,
+    output: OutputFormat,
+) -> Result<PathBuf, String> {
+    if download.download_url.is_empty() {
+        return Err(format!(
+            "No archive found in the assets of {}",
+            download.version
+        ));
+    }
+    fs::create_dir_all(temp_dir)
+        .map_err(|e| format!("Failed creating {}: {}", temp_dir.display(), e))?;
+    let mut temp_dir = temp_dir.to_path_buf();
 
-    temp_dir.push(if download.download_url.ends_with("tar.gz") {
-        format!("{}.tar.gz", &download.version)
-    } else if download.download_url.ends_with("tar.xz") {
-        format!("{}.tar.xz", &download.version)
-    } else {
-        eprintln!("Downloaded file wasn't of the expected type. (tar.(gz/xz)");
-        std::process::exit(1)
+    // The archive format is detected from its content when unpacking, the extension is only kept for the user
+    temp_dir.push(match files::archive_extension(&download.download_url) {
+        Some(extension) => format!("{}.{}", &download.version, extension),
+        None => download.version.clone(),
     });
 
-    let git_hash = files::download_file_into_memory(&download.sha512sum_url)
-        .await
-        .unwrap();
+    let git_hash = download_sha512sum(&download).await?;
 
     if temp_dir.exists() {
-        fs::remove_file(&temp_dir).unwrap();
+        fs::remove_file(&temp_dir)
+            .map_err(|e| format!("Failed removing {}: {}", temp_dir.display(), e))?;
+    }
+
+    let (progress, done, progress_bar) = spawn_progress_bar(
+        &download,
+        format!(
+            "Downloaded {} to {}",
+            download.download_url,
+            temp_dir.display()
+        ),
+    );
+
+    files::download_file_progress(
+        download.download_url,
+        download.size,
+        temp_dir.clone().as_path(),
+        progress,
+        done,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    // The progress bar needs to be closed before printing
+    let _ = progress_bar.join();
+
+    match git_hash {
+        Some(git_hash) => {
+            output.status("Checking file integrity");
+            if !files::hash_check_file(temp_dir.to_string_lossy().to_string(), git_hash)
+                .map_err(|e| e.to_string())?
+            {
+                return Err("Failed checking file hash".to_string());
+            }
+        }
+        None => output.status(&format!(
+            "No sha512sum published for {}, skipping integrity check",
+            download.version
+        )),
+    }
+
+    Ok(temp_dir)
+}
+
+/// Downloads a release and unpacks it into the install directory while it is downloaded, without storing the archive.
+/// Nothing is installed if the archive doesn't match its sha512sum
+pub(crate) async fn download_and_unpack(
+    download: Download,
+    install_dir: &Path,
+    wine_version: &Variant,
+    output: OutputFormat,
+) -> Result<(), String> {
+    if download.download_url.is_empty() {
+        return Err(format!(
+            "No archive found in the assets of {}",
+            download.version
+        ));
+    }
+    fs::create_dir_all(install_dir)
+        .map_err(|e| format!("Failed creating {}: {}", install_dir.display(), e))?;
+
+    let git_hash = download_sha512sum(&download).await?;
+    if git_hash.is_none() {
+        output.status(&format!(
+            "No sha512sum published for {}, skipping integrity check",
+            download.version
+        ));
+    }
+
+    let (progress, done, progress_bar) = spawn_progress_bar(
+        &download,
+        format!(
+            "Downloaded and unpacked {} into {}",
+            download.download_url,
+            install_dir.display()
+        ),
+    );
+    let release = wine_version.asset_rules().archive_release(&download.version);
+    let unpacked = files::download_and_unpack(
+        download.download_url,
+        download.size,
+        git_hash,
+        Some(release),
+        install_dir,
+        progress,
+        done,
+    )
+    .await;
+    let _ = progress_bar.join();
+    unpacked.map_err(|e| format!("{:#}", e))?;
+
+    output.status(&format!(
+        "Done! Restart {}. {} installed in {}",
+        wine_version.intended_application(),
+        wine_version,
+        install_dir.to_string_lossy(),
+    ));
+    Ok(())
+}
+
+/// Downloads the sha512sum file of a release. Some variants don't publish one, their downloads can't be checked
+async fn download_sha512sum(download: &Download) -> Result<Option<String>, String> {
+    if download.sha512sum_url.is_empty() {
+        return Ok(None);
     }
+    files::download_file_into_memory(&download.sha512sum_url)
+        .await
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
 
+/// Starts a progress bar for the download in another thread, following the returned progress trackers.
+/// The thread ends when the download is marked as done
+fn spawn_progress_bar(
+    download: &Download,
+    finished_message: String,
+) -> (Arc<AtomicUsize>, Arc<AtomicBool>, JoinHandle<()>) {
     let (progress, done) = files::create_progress_trackers();
     let progress_read = Arc::clone(&progress);
     let done_read = Arc::clone(&done);
     let url = String::from(&download.download_url);
-    let tmp_dir = String::from(temp_dir.to_str().unwrap());
+    let size = download.size;
 
     // start ProgressBar in another thread
-    thread::spawn(move || {
-        let pb = ProgressBar::with_draw_target(
-            Some(download.size),
-            ProgressDrawTarget::stderr_with_hz(20),
-        );
+    let progress_bar = thread::spawn(move || {
+        let pb = ProgressBar::with_draw_target(Some(size), ProgressDrawTarget::stderr_with_hz(20));
         pb.set_style(ProgressStyle::default_bar()
         .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec})").unwrap()
         .progress_chars("#>-"));
-        pb.set_message(format!("Downloading {}", url.split('/').last().unwrap()));
+        pb.set_message(format!(
+            "Downloading {}",
+            url.split('/').next_back().unwrap()
+        ));
         let wait_time = Duration::from_millis(50); // 50ms wait is about 20Hz
         loop {
             let newpos = progress_read.load(Ordering::Relaxed);
@@ -64,162 +195,312 @@ pub(crate) async fn download_file(download: Download) -> Result<PathBuf, String>
             }
             thread::sleep(wait_time);
         }
-        pb.set_message(format!("Downloaded {url} to {tmp_dir}"));
+        pb.set_message(finished_message);
         pb.abandon(); // closes progress bar without blanking terminal
-
-        println!("Checking file integrity"); // This is being printed here because the progress bar needs to be closed before printing.
     });
-
-    files::download_file_progress(
-        download.download_url,
-        download.size,
-        temp_dir.clone().as_path(),
-        progress,
-        done,
-    )
-    .await
-    .unwrap();
-
-    if !files::hash_check_file(temp_dir.to_str().unwrap().to_string(), git_hash).unwrap() {
-        return Err("Failed checking file hash".to_string());
-    }
-
-    Ok(temp_dir)
+    (progress, done, progress_bar)
 }
 
 pub(crate) async fn unpack_file(
     dowaload_path: &Path,
-    install_path: &str,
+    install_dir: &Path,
     wine_version: &Variant,
+    version: &str,
+    output: OutputFormat,
 ) -> Result<(), String> {
-    let install_dir = utils::expand_tilde(install_path).unwrap();
+    fs::create_dir_all(install_dir)
+        .map_err(|e| format!("Failed creating {}: {}", install_dir.display(), e))?;
 
-    fs::create_dir_all(&install_dir).unwrap();
-
-    println!("Unpacking files into install location. Please wait");
-    files::decompress(dowaload_path, install_dir.as_path()).unwrap();
-    println!(
+    output.status("Unpacking files into install location. Please wait");
+    let release = wine_version.asset_rules().archive_release(version);
+    files::decompress(dowaload_path, install_dir, Some(&release)).map_err(|e| e.to_string())?;
+    output.status(&format!(
         "Done! Restart {}. {} installed in {}",
         wine_version.intended_application(),
         wine_version,
         install_dir.to_string_lossy(),
-    );
+    ));
     Ok(())
 }
 
-pub async fn run_quick_downloads() {
-    let found_apps = apps::list_installed_apps();
+/// Downloads the latest version of the preferred variant of every detected app, and of the custom variants targeting it.
+/// Only the apps listed in the config's default_apps are updated when it is set
+pub async fn run_quick_downloads(config: &Config, output: OutputFormat) {
+    let found_apps: Vec<apps::AppInstallations> = apps::list_installed_apps()
+        .into_iter()
+        .filter(|app_inst| config.is_default_app(app_inst.into_app()))
+        .collect();
     if found_apps.is_empty() {
-        println!("No apps found. Please install at least one app before using this feature.");
+        output.status("No apps found. Please install at least one app before using this feature.");
+        output.records(&QuickUpdateReport {
+            detected: found_apps,
+            results: vec![],
+        });
         return;
     }
-    println!(
+    output.status(&format!(
         "Found the following apps: {}",
         found_apps
             .iter()
             .map(|app| app.to_string())
             .collect::<Vec<String>>()
             .join(", ")
-    );
+    ));
 
+    let mut results = vec![];
     for app_inst in &found_apps {
-        let wine_version = app_inst.into_app().app_wine_version();
-        let destination = app_inst.default_install_dir().to_string();
-        println!(
-            "\nQuick Download: {} for {} into -> {}",
-            wine_version,
-            app_inst.into_app(),
-            destination
-        );
-
-        // Get the latest Download info for the wine_version
-        let download = match github::list_releases(&wine_version.get_github_parameters()).await {
-            // Get the Download info from the first item on the list, the latest version
-            Ok(release_list) => release_list[0].get_download_info(),
-            Err(e) => {
-                eprintln!("Failed to fetch Github data, make sure you're connected to the internet.\nError: {}", e);
-                std::process::exit(1)
-            }
-        };
+        let destination = config.install_dir(app_inst);
+        let preferred = config.preferred_variant(app_inst);
+        let custom_variants = config
+            .custom_variants_for(app_inst)
+            .into_iter()
+            .filter(|custom| custom.to_string() != preferred.to_string());
+        for wine_version in std::iter::once(preferred.clone()).chain(custom_variants) {
+            results
+                .push(quick_download(app_inst, &wine_version, &destination, config, output).await);
+        }
+    }
+    let failed = results.iter().any(|result| !result.success);
+    output.records(&QuickUpdateReport {
+        detected: found_apps,
+        results,
+    });
+    if failed {
+        std::process::exit(1)
+    }
+}
+
+/// Adds the versions pinned with `--pin` to the config, replacing the ones it pins for the same app or variant.
+/// Each pin is `<app>=<tag>` or `<variant>=<tag>`
+pub(crate) fn apply_pins(config: &mut Config, pins: &[String]) -> Result<(), String> {
+    for pin in pins {
+        let (name, tag) = pin
+            .split_once('=')
+            .filter(|(name, tag)| !name.is_empty() && !tag.is_empty())
+            .ok_or_else(|| format!("Invalid pin {}, expected <app or variant>=<tag>", pin))?;
+        if let Ok(app) = AppArg::from_str(name, true) {
+            config
+                .pinned_versions
+                .insert(apps::App::from(app), tag.to_string());
+        } else if let Some(variant) = config.find_variant(name) {
+            config
+                .pinned_variant_versions
+                .insert(variant.to_string(), tag.to_string());
+        } else {
+            return Err(format!(
+                "Invalid pin {}, {} is not an app or a variant",
+                pin, name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Downloads and unpacks the latest release of a variant into an app installation, or the one pinned in the config
+async fn quick_download(
+    app_inst: &apps::AppInstallations,
+    wine_version: &Variant,
+    destination: &Path,
+    config: &Config,
+    output: OutputFormat,
+) -> InstallResult {
+    output.status(&format!(
+        "\nQuick Download: {} for {} into -> {}",
+        wine_version,
+        app_inst.into_app(),
+        destination.display()
+    ));
+
+    // Clean up after installs interrupted in a previous run
+    match files::remove_staging_leftovers(destination) {
+        Ok(removed) if !removed.is_empty() => output.status(&format!(
+            "Removed unfinished installs: {}",
+            removed.join(", ")
+        )),
+        _ => {}
+    }
+
+    // Get the Download info from the pinned version, or the latest one
+    let source = wine_version.get_github_parameters();
+    let token = config.github_token();
+    let pinned = config.pinned_version(app_inst, wine_version);
+    let release = match pinned {
+        Some(tag) => github::get_release_by_tag(&source, tag, token.as_deref())
+            .await
+            .map_err(|e| {
+                if e.is_not_found() {
+                    format!(
+                        "Pinned version {} of {} was not found on its releases page",
+                        tag, wine_version
+                    )
+                } else {
+                    format!("Failed to fetch Github data, make sure you're connected to the internet.\nError: {}", e)
+                }
+            }),
+        None => github::get_latest_release(&source, token.as_deref()).await.map_err(|e| {
+            format!("Failed to fetch Github data, make sure you're connected to the internet.\nError: {}", e)
+        }),
+    };
+    let release = match release {
+        Ok(release) => release,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return InstallResult {
+                variant: Some(wine_version.clone()),
+                version: pinned.unwrap_or_default().to_string(),
+                install_dir: destination.to_path_buf(),
+                up_to_date: false,
+                success: false,
+                error: Some(error),
+            };
+        }
+    };
+    let download = release.get_download_info(wine_version);
+    let version = download.version.clone();
 
-        let file = download_file(download).await.unwrap();
-        unpack_file(&file, &destination, &wine_version)
+    // Skip the download when the version is already installed in the install directory from the config.
+    // Folders are matched by the variant's folder name, see files::installed_releases
+    let up_to_date = files::installed_releases(
+        destination,
+        &wine_version.asset_rules(),
+        std::slice::from_ref(&version),
+    )
+    .is_ok_and(|installed| !installed.is_empty());
+    let unpacked = if up_to_date {
+        output.status(&format!("{} {} is up to date", wine_version, version));
+        Ok(())
+    } else {
+        download_and_unpack(download, destination, wine_version, output)
             .await
-            .unwrap_or_else(|e| {
+            .map_err(|e| {
+                eprintln!(
+                    "Failed installing {} into {}. Error: {}",
+                    version,
+                    destination.display(),
+                    e
+                );
+                e
+            })
+    };
+
+    // Remove the oldest versions according to the keep_versions policy, never the pinned one
+    if let Some(keep) = config.keep_versions {
+        let tags: Vec<String> = match github::list_releases(
+            &source,
+            Some(constants::RELEASES_PER_PAGE),
+            token.as_deref(),
+        )
+        .await
+        {
+            Ok(release_list) => release_list
+                .iter()
+                .map(|r| r.tag_name.clone())
+                .filter(|tag| Some(tag.as_str()) != pinned)
+                .collect(),
+            Err(e) => {
                 eprintln!(
-                    "Failed unpacking file {} into {}. Error: {}",
-                    file.to_string_lossy(),
-                    destination,
+                    "Failed to fetch Github data, old versions were kept.\nError: {}",
                     e
                 );
-            });
+                vec![]
+            }
+        };
+        match files::prune_old_versions(destination, &wine_version.asset_rules(), &tags, keep) {
+            Ok(removed) if !removed.is_empty() => {
+                output.status(&format!("Removed old versions: {}", removed.join(", ")))
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed removing old versions. Error: {}", e),
+        }
+    }
+
+    InstallResult {
+        variant: Some(wine_version.clone()),
+        version,
+        install_dir: destination.to_path_buf(),
+        up_to_date,
+        success: unpacked.is_ok(),
+        error: unpacked.err(),
     }
 }
 
 /// Start the Download for the selected app
 /// If no app is provided, the user is prompted for which version of Wine/Proton to use and what directory to extract to
-pub async fn download_to_selected_app(app: Option<apps::App>) {
-    // Get the version of Wine/Proton to install
-    let wine_version = match app {
-        // Use the default for the app
-        Some(app) => app.app_wine_version(),
-        // Or have the user select which one
-        None => Select::new(
-            "Choose the variant you want to install:",
-            variants::ALL_VARIANTS.to_vec(),
-        )
-        .prompt()
-        .unwrap_or_else(|_| std::process::exit(0)),
-    };
-
-    // Get the folder to install Wine/Proton into
-    let install_dir: String = match app {
+pub async fn download_to_selected_app(app: Option<apps::App>, config: &Config) {
+    // Get the version of Wine/Proton to install and the folder to install it into
+    let (wine_version, install_dir): (Variant, PathBuf) = match app {
         // If the user selected an app (Steam/Lutris)...
-        Some(app) => match app.detect_installation_method() {
-            installed_apps if installed_apps.len() == 0 => {
-                println!("Install location for selected app(s) not found. Exiting.");
-                std::process::exit(0);
-            }
-
+        Some(app) => {
+            let app_inst = match app.detect_installation_method() {
+                installed_apps if installed_apps.is_empty() => {
+                    println!("Install location for selected app(s) not found. Exiting.");
+                    std::process::exit(0);
+                }
 
-            // Figure out which versions of the App the user has (Native/Flatpak)
-            installed_apps if installed_apps.len() == 1 => {
-                println!(
-                    "Detected {}. Installing to {}",
-                    installed_apps[0],
-                    installed_apps[0].default_install_dir()
-                );
-                installed_apps[0].default_install_dir().to_string()
-            }
-            // If the user has more than one installation method, ask them which one they would like to use
-            installed_apps => Select::new(
-                "Detected several app versions, which would you like to use?",
-                installed_apps,
-            )
-            .prompt()
-            .unwrap_or_else(|_| std::process::exit(0))
-            .default_install_dir()
-            .to_string(),
-        },
-        // If the user didn't select an app, ask them what directory they want to install to
-        None => Text::new("Installation path:")
-            .with_autocomplete(file_path::FilePathCompleter::default())
-            .with_help_message(&format!(
-                "Current directory: {}",
-                &std::env::current_dir()
-                    .unwrap_or_else(|_| std::process::exit(0))
-                    .to_string_lossy()
-            ))
-            .with_default(
-                &std::env::current_dir()
-                    .unwrap_or_else(|_| std::process::exit(0))
-                    .to_string_lossy(),
+                // Figure out which versions of the App the user has (Native/Flatpak/Snap)
+                installed_apps if installed_apps.len() == 1 => {
+                    println!(
+                        "Detected {}. Installing to {}",
+                        installed_apps[0],
+                        config.install_dir(&installed_apps[0]).display()
+                    );
+                    installed_apps[0].clone()
+                }
+                // If the user has more than one installation method, ask them which one they would like to use
+                installed_apps => Select::new(
+                    "Detected several app versions, which would you like to use?",
+                    installed_apps,
+                )
+                .prompt()
+                .unwrap_or_else(|_| std::process::exit(0)),
+            };
+            // Use the preferred variant from the config, or let the user pick if the installation supports several variants
+            let wine_version = match config.compatible_variants(&app_inst) {
+                _ if config.preferred_variants.contains_key(&app) => {
+                    config.preferred_variant(&app_inst)
+                }
+                variants if variants.len() == 1 => variants[0].clone(),
+                variants => Select::new("Choose the variant you want to install:", variants)
+                    .prompt()
+                    .unwrap_or_else(|_| std::process::exit(0)),
+            };
+            (wine_version, config.install_dir(&app_inst))
+        }
+        // If the user didn't select an app, ask them which variant they want and what directory to install to
+        None => (
+            Select::new(
+                "Choose the variant you want to install:",
+                config.all_variants(),
             )
             .prompt()
             .unwrap_or_else(|_| std::process::exit(0)),
+            Text::new("Installation path:")
+                .with_autocomplete(file_path::FilePathCompleter::default())
+                .with_help_message(&format!(
+                    "Current directory: {}",
+                    &std::env::current_dir()
+                        .unwrap_or_else(|_| std::process::exit(0))
+                        .to_string_lossy()
+                ))
+                .with_default(
+                    &std::env::current_dir()
+                        .unwrap_or_else(|_| std::process::exit(0))
+                        .to_string_lossy(),
+                )
+                .prompt()
+                .map(|path| utils::expand_tilde(path).unwrap())
+                .unwrap_or_else(|_| std::process::exit(0)),
+        ),
     };
 
-    let release_list = match github::list_releases(&wine_version.get_github_parameters()).await {
+    // List every release, so older versions can be picked too
+    let release_list = match github::list_releases(
+        &wine_version.get_github_parameters(),
+        None,
+        config.github_token().as_deref(),
+    )
+    .await
+    {
         Ok(data) => data,
         Err(e) => {
             eprintln!("Failed to fetch Github data, make sure you're connected to the internet.\nError: {}", e);
@@ -247,37 +528,36 @@ pub async fn download_to_selected_app(app: Option<apps::App>) {
     // Prepare the download for the user's chosen releases/versions
     // TODO Look into using async in a way to download multiple files at once, would need to .join all the download_file() 'Futures'
     for release in &release_list {
-        match download_file(release.get_download_info()).await {
-            Ok(file) => {
-                // TODO: should just upack once and copy to all folders
-                unpack_file(&file, &install_dir, &wine_version)
-                    .await
-                    .unwrap();
-            }
-            Err(e) => {
-                eprintln!(
-                    "Error downloading {}, make sure you're connected to the internet\nError: {}",
-                    release.tag_name, e
-                )
-            }
+        if let Err(e) = download_and_unpack(
+            release.get_download_info(&wine_version),
+            &install_dir,
+            &wine_version,
+            OutputFormat::Text,
+        )
+        .await
+        {
+            eprintln!(
+                "Error installing {}, make sure you're connected to the internet\nError: {}",
+                release.tag_name, e
+            )
         }
     }
 }
 
 /// Checks if the selected Release/version is already installed.
 /// Will prompt the user to overwrite existing files
-async fn check_if_already_downloaded(release_list: &mut Vec<Release>, install_dir: &str) {
+async fn check_if_already_downloaded(release_list: &mut Vec<Release>, install_dir: &Path) {
     release_list.retain(|release| {
         // Check if versions exist in disk.
         // If they do, ask the user if it should be overwritten
-        !(files::check_if_exists(&install_dir, &release.tag_name)
-            && !helper_menus::confirm_menu(
+        !files::check_if_exists(install_dir, &release.tag_name)
+            || helper_menus::confirm_menu(
                 format!(
                     "Version {} exists in the installation path. Overwrite?",
                     &release.tag_name
                 ),
                 String::from("If you choose yes, you will re-install it."),
                 false,
-            ))
+            )
     });
 }
@@ -4,20 +4,34 @@ use inquire::Select;
 
 use std::fmt;
 
-use libprotonup::apps::App;
+use libprotonup::{apps::App, config::Config};
 
+mod commands;
 mod download;
 mod file_path;
 mod helper_menus;
 mod manage_apps;
+mod output;
 
 use manage_apps::manage_apps_routine;
 
+/// Install and manage GE-Proton, Wine-GE and other custom Proton/Wine builds
 #[derive(Debug, Parser)]
 struct Opt {
     /// Skip Menu, auto detect apps and download using default parameters
     #[arg(short, long)]
     quick_download: bool,
+
+    /// Install this release instead of the latest one with --quick-download, for an app or a variant, like steam=GE-Proton8-25 or WineGE=GE-Proton8-26. Can be repeated
+    #[arg(long, value_name = "APP|VARIANT=TAG", requires = "quick_download")]
+    pin: Vec<String>,
+
+    /// Output format, json prints machine readable records on stdout
+    #[arg(long, value_enum, global = true, default_value_t)]
+    output: output::OutputFormat,
+
+    #[command(subcommand)]
+    command: Option<commands::Command>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -26,6 +40,8 @@ enum InitialMenu {
     QuickUpdate,
     DownloadForSteam,
     DownloadForLutris,
+    DownloadForHeroic,
+    DownloadForBottles,
     DownloadIntoCustomLocation,
     ManageExistingInstallations,
 }
@@ -36,6 +52,8 @@ impl InitialMenu {
         Self::QuickUpdate,
         Self::DownloadForSteam,
         Self::DownloadForLutris,
+        Self::DownloadForHeroic,
+        Self::DownloadForBottles,
         Self::DownloadIntoCustomLocation,
         Self::ManageExistingInstallations,
     ];
@@ -45,8 +63,12 @@ impl fmt::Display for InitialMenu {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Self::QuickUpdate => write!(f, "Quick Update (detect apps and auto download)"),
-            Self::DownloadForSteam => write!(f, "Download GE-Proton for Steam"),
+            Self::DownloadForSteam => write!(f, "Download GE-Proton/Luxtorpeda/Boxtron for Steam"),
             Self::DownloadForLutris => write!(f, "Download GE-Proton/Wine-GE for Lutris"),
+            Self::DownloadForHeroic => {
+                write!(f, "Download GE-Proton/Wine-GE for Heroic Games Launcher")
+            }
+            Self::DownloadForBottles => write!(f, "Download Wine-GE for Bottles"),
             Self::DownloadIntoCustomLocation => {
                 write!(f, "Download GE-Proton/Wine-GE into custom location")
             }
@@ -57,10 +79,32 @@ impl fmt::Display for InitialMenu {
 
 #[tokio::main]
 async fn main() {
+    let Opt {
+        quick_download,
+        pin,
+        output,
+        command,
+    } = Opt::parse();
+    let mut config = Config::load().unwrap_or_else(|e| {
+        eprintln!("Failed to load the config file.\nError: {:?}", e);
+        std::process::exit(1)
+    });
+    if let Err(e) = download::apply_pins(&mut config, &pin) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1)
+    }
+    // run a non interactive command and skip InitialMenu
+    if let Some(command) = command {
+        if let Err(e) = commands::run_command(command, &config, output).await {
+            eprintln!("Error: {}", e);
+            std::process::exit(1)
+        }
     // run quick downloads and skip InitialMenu
-    let Opt { quick_download } = Opt::parse();
-    if quick_download {
-        download::run_quick_downloads().await
+    } else if quick_download {
+        download::run_quick_downloads(&config, output).await
+    } else if output == output::OutputFormat::Json {
+        eprintln!("JSON output is only available with --quick-download or a command");
+        std::process::exit(1)
     } else {
         let answer: InitialMenu = Select::new(
             "ProtonUp Menu: Choose your action:",
@@ -72,11 +116,13 @@ async fn main() {
 
         // Set parameters based on users choice
         match answer {
-            InitialMenu::QuickUpdate => download::run_quick_downloads().await,
-            InitialMenu::DownloadForSteam => download::download_to_selected_app(Some(App::Steam)).await,
-            InitialMenu::DownloadForLutris => download::download_to_selected_app(Some(App::Lutris)).await,
-            InitialMenu::DownloadIntoCustomLocation => download::download_to_selected_app(None).await,
-            InitialMenu::ManageExistingInstallations => manage_apps_routine(),
+            InitialMenu::QuickUpdate => download::run_quick_downloads(&config, output).await,
+            InitialMenu::DownloadForSteam => download::download_to_selected_app(Some(App::Steam), &config).await,
+            InitialMenu::DownloadForLutris => download::download_to_selected_app(Some(App::Lutris), &config).await,
+            InitialMenu::DownloadForHeroic => download::download_to_selected_app(Some(App::HeroicGamesLauncher), &config).await,
+            InitialMenu::DownloadForBottles => download::download_to_selected_app(Some(App::Bottles), &config).await,
+            InitialMenu::DownloadIntoCustomLocation => download::download_to_selected_app(None, &config).await,
+            InitialMenu::ManageExistingInstallations => manage_apps_routine(&config),
         }
     }
 }
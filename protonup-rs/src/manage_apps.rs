@@ -1,5 +1,5 @@
 use inquire::MultiSelect;
-use libprotonup::{apps, files};
+use libprotonup::{apps, config::Config, files};
 use std::fmt;
 
 use super::helper_menus::{confirm_menu, multiple_select_menu};
@@ -15,8 +15,15 @@ static APP_VARIANTS_WITH_DETECT: &[ManageAppsMenuOptions] = &[
     ManageAppsMenuOptions::DetectAll,
     ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::Steam),
     ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::SteamFlatpak),
+    ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::SteamSnap),
     ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::Lutris),
     ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::LutrisFlatpak),
+    ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::HeroicProton),
+    ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::HeroicProtonFlatpak),
+    ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::HeroicWine),
+    ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::HeroicWineFlatpak),
+    ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::Bottles),
+    ManageAppsMenuOptions::AppInstallations(apps::AppInstallations::BottlesFlatpak),
 ];
 
 impl fmt::Display for ManageAppsMenuOptions {
@@ -46,16 +53,23 @@ fn manage_menu() -> Vec<ManageAppsMenuOptions> {
     }
 }
 
-pub(crate) fn manage_apps_routine() {
-    let mut apps = vec![];
-
+pub(crate) fn manage_apps_routine(config: &Config) {
     let choices = manage_menu();
 
-    if choices.contains(&ManageAppsMenuOptions::DetectAll) {
-        apps = apps::APP_INSTALLATIONS_VARIANTS.to_vec();
-    }
+    let apps = if choices.contains(&ManageAppsMenuOptions::DetectAll) {
+        apps::APP_INSTALLATIONS_VARIANTS.to_vec()
+    } else {
+        choices
+            .into_iter()
+            .filter_map(|choice| match choice {
+                ManageAppsMenuOptions::AppInstallations(app_inst) => Some(app_inst),
+                ManageAppsMenuOptions::DetectAll => None,
+            })
+            .collect()
+    };
     for app in apps {
-        let versions = match app.list_installed_versions() {
+        let install_dir = config.install_dir(&app);
+        let versions = match files::list_folders_in_path(&install_dir) {
             Ok(versions) => versions,
             Err(_) => {
                 println!("App {} not found in your system, skipping... ", app);
@@ -66,15 +80,11 @@ pub(crate) fn manage_apps_routine() {
             println!("No versions found for {}, skipping... ", app);
             continue;
         }
-        let delete_versions = match multiple_select_menu(
+        let delete_versions = multiple_select_menu(
             &format!("Select the versions you want to DELETE from {}", app),
             versions,
-        ) {
-            Ok(versions) => versions,
-            Err(_) => {
-                vec![]
-            }
-        };
+        )
+        .unwrap_or_default();
 
         if delete_versions.is_empty() {
             println!("Zero versions selected for {}, skipping...\n", app);
@@ -86,20 +96,13 @@ pub(crate) fn manage_apps_routine() {
             true,
         ) {
             for version in delete_versions {
-                files::remove_dir_all(&format!("{}{}", &app.default_install_dir(), &version))
-                    .map_or_else(
-                        |e| {
-                            eprintln!(
-                                "Error deleting {}{}: {}",
-                                &app.default_install_dir(),
-                                &version,
-                                e
-                            )
-                        },
-                        |_| {
-                            println!("{} {} deleted successfully", &app, &version);
-                        },
-                    );
+                let version_dir = install_dir.join(&version);
+                files::remove_dir_all(&version_dir).map_or_else(
+                    |e| eprintln!("Error deleting {}: {}", version_dir.display(), e),
+                    |_| {
+                        println!("{} {} deleted successfully", &app, &version);
+                    },
+                );
             }
         }
     }
@@ -0,0 +1,88 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use std::path::PathBuf;
+
+use libprotonup::{
+    apps::{App, AppInstallations},
+    variants::Variant,
+};
+
+/// Format of the program's output
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// JSON records on stdout, human readable messages are moved to stderr
+    Json,
+}
+
+impl OutputFormat {
+    /// Prints a human readable message, to stdout in text mode and to stderr in JSON mode so it doesn't mix with the records
+    pub(crate) fn status(&self, message: &str) {
+        match self {
+            OutputFormat::Text => println!("{}", message),
+            OutputFormat::Json => eprintln!("{}", message),
+        }
+    }
+
+    /// Prints the records as JSON, does nothing in text mode where they were already described with `status`
+    pub(crate) fn records<T: Serialize>(&self, records: &T) {
+        if let OutputFormat::Json = self {
+            match serde_json::to_string_pretty(records) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("Failed to serialize the output.\nError: {}", e),
+            }
+        }
+    }
+}
+
+/// A detected app installation and the versions installed in it
+#[derive(Debug, Serialize)]
+pub(crate) struct InstalledVersions {
+    pub(crate) installation: AppInstallations,
+    pub(crate) app: App,
+    pub(crate) install_dir: PathBuf,
+    pub(crate) versions: Vec<String>,
+}
+
+/// The outcome of installing a version
+#[derive(Debug, Serialize)]
+pub(crate) struct InstallResult {
+    /// None for local archives, which can be of any variant
+    pub(crate) variant: Option<Variant>,
+    pub(crate) version: String,
+    pub(crate) install_dir: PathBuf,
+    /// The version was already installed, nothing was downloaded
+    pub(crate) up_to_date: bool,
+    pub(crate) success: bool,
+    pub(crate) error: Option<String>,
+}
+
+/// A removed version
+#[derive(Debug, Serialize)]
+pub(crate) struct RemoveResult {
+    pub(crate) version: String,
+    pub(crate) install_dir: PathBuf,
+}
+
+/// The installed version of a variant compared with its latest release
+#[derive(Debug, Serialize)]
+pub(crate) struct UpdateCheck {
+    pub(crate) installation: AppInstallations,
+    pub(crate) variant: Variant,
+    pub(crate) install_dir: PathBuf,
+    /// Newest installed release, None when no release of the variant is installed
+    pub(crate) installed: Option<String>,
+    pub(crate) latest: String,
+    pub(crate) releases_behind: Option<usize>,
+    pub(crate) outdated: bool,
+}
+
+/// Everything Quick Update detected and installed
+#[derive(Debug, Serialize)]
+pub(crate) struct QuickUpdateReport {
+    pub(crate) detected: Vec<AppInstallations>,
+    pub(crate) results: Vec<InstallResult>,
+}